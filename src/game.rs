@@ -1,15 +1,20 @@
-use core::ops::Range;
-
 use agb::{
     display::{
-        object::{OamIterator, ObjectUnmanaged, SpriteLoader, SpriteVram, Tag},
-        tiled::{InfiniteScrolledMap, VRamManager},
+        affine::AffineMatrix,
+        object::{
+            AffineMatrixInstance, AffineMode, OamIterator, ObjectUnmanaged, Sprite, SpriteLoader,
+            SpriteVram,
+        },
+        tiled::{InfiniteScrolledMap, MapLoan, RegularMap, TileSetting, TiledMap, VRamManager},
+        window::{WinIn, Windows},
+        HEIGHT, WIDTH,
     },
     fixnum::{num, Num, Rect, Vector2D},
     hash_map::HashMap,
     input::{Button, ButtonController},
     mgba::Mgba,
-    sound::mixer::Mixer,
+    rng::RandomNumberGenerator,
+    sound::mixer::{ChannelId, Mixer},
 };
 use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
 
@@ -22,158 +27,568 @@ pub mod resource {
             palette16::Palette16,
             tile_data::TileData,
         },
-        fixnum::{Rect, Vector2D},
+        fixnum::{Num, Rect, Vector2D},
         hash_map::HashMap,
-        sound::mixer::SoundChannel,
+        sound::mixer::{Frequency, SoundChannel},
     };
     use alloc::vec::Vec;
 
+    use super::Skin;
+
     const SPRITES: &Graphics = agb::include_aseprite!("assets/gfx/dino.aseprite");
     pub(super) const DINO: &Tag = SPRITES.tags().get("Dino");
     pub(super) const BIRD: &Tag = SPRITES.tags().get("Bird");
-    pub(super) const CACTUS: &Sprite = SPRITES.tags().get("Cactus").sprite(0);
+    pub(super) const DINO_RED: &Tag = SPRITES.tags().get("DinoRed");
+    // The run frames washed out pale and red, shown for a moment when a life is lost
+    pub(super) const DINO_HIT: &Tag = SPRITES.tags().get("DinoHit");
+    // The run cycle with the neck tucked in, for each skin
+    pub(super) const DINO_DUCK: &Tag = SPRITES.tags().get("DinoDuck");
+    pub(super) const DINO_RED_DUCK: &Tag = SPRITES.tags().get("DinoRedDuck");
+    // Idle sway frames: lean left, upright, lean right
+    pub(super) const CACTUS: &Tag = SPRITES.tags().get("CactusSway");
+    pub(super) const CACTUS_SMALL: &Tag = SPRITES.tags().get("CactusSmallSway");
+    pub(super) const CACTUS_TALL: &Tag = SPRITES.tags().get("CactusTallSway");
+    pub(super) const SPRING: &Tag = SPRITES.tags().get("Spring");
+    pub(super) const SPIKE: &Sprite = SPRITES.tags().get("Spike").sprite(0);
 
     const FONT_SPRITES: &Graphics = agb::include_aseprite!("assets/gfx/font.aseprite");
-    pub(super) const CHAR_SPRITE_KEYS: [&'static str; 16] = [
-        "G", "A", "M", "E", "O", "V", "R", "S", "C", "H", "I", "T", "P", "?", "U", "D",
+    // Glyphs used during a run, by the HUD, hints and toasts, which stay in VRAM all the time
+    pub(super) const CHAR_SPRITE_KEYS: [&str; 26] = [
+        "S", "C", "O", "R", "E", "H", "I", ":", "?", "M", "X", "G", "A", "V", "T", "P", "U", "D",
+        "L", "N", "B", "W", "K", "J", "Q", "!",
     ];
+    // Glyphs only used by the pause/game over/shop overlays, loaded while those are shown
+    pub(super) const OVERLAY_CHAR_SPRITE_KEYS: [&str; 4] = [",", "F", "Y", "'"];
     pub(super) const NUMBER: &Tag = FONT_SPRITES.tags().get("Number");
 
+    const PARTICLE_SPRITES: &Graphics = agb::include_aseprite!("assets/gfx/particles.aseprite");
+    pub(super) const DUST: &Tag = PARTICLE_SPRITES.tags().get("Dust");
+    pub(super) const STAR: &Tag = PARTICLE_SPRITES.tags().get("Star");
+    pub(super) const COIN: &Tag = PARTICLE_SPRITES.tags().get("Coin");
+    pub(super) const MAGNET: &Sprite = PARTICLE_SPRITES.tags().get("Magnet").sprite(0);
+    pub(super) const WARNING: &Sprite = PARTICLE_SPRITES.tags().get("Warning").sprite(0);
+    pub(super) const HEART: &Sprite = PARTICLE_SPRITES.tags().get("Heart").sprite(0);
+    pub(super) const CONFETTI: &Tag = PARTICLE_SPRITES.tags().get("Confetti");
+    pub(super) const FEATHER: &Sprite = PARTICLE_SPRITES.tags().get("Feather").sprite(0);
+    pub(super) const SNOWFLAKE: &Sprite = PARTICLE_SPRITES.tags().get("Snowflake").sprite(0);
+    pub(super) const MOON: &Sprite = SPRITES.tags().get("Moon").sprite(0);
+    pub(super) const MOON_POSITION: (i32, i32) = (150, 24);
+    // Stars are revealed in this order as the night falls
+    pub(super) const STAR_POSITIONS: [(i32, i32); 7] = [
+        (40, 36),
+        (200, 52),
+        (96, 28),
+        (132, 60),
+        (16, 64),
+        (176, 34),
+        (68, 54),
+    ];
+
     // Load background tiles as `bg_tiles` module
-    agb::include_background_gfx!(bg_tiles, tiles => "assets/gfx/dino_background.bmp");
+    // Both share one set of palettes, so the rain layer's colours don't clash with the ground's
+    agb::include_background_gfx!(
+        bg_tiles,
+        tiles => "assets/gfx/dino_background.bmp",
+        rain => "assets/gfx/rain.png"
+    );
     const TILE_MAP_CSV_STR: &str = include_str!("../assets/tilemap/dino_map.csv");
 
     pub const BG_TILES_DATA: TileData = bg_tiles::tiles;
     pub const BG_PALETTES: &[Palette16] = bg_tiles::PALETTES;
+    pub const RAIN_TILES_DATA: TileData = bg_tiles::rain;
+    pub const RAIN_TILE_COUNT: usize = 2;
+
+    // Steps between the day and night palettes when one fades into the other
+    pub const NIGHT_BLEND_STEPS: u16 = 16;
+
+    // The background palettes as they look now: `night_step` of the way (out of
+    // `NIGHT_BLEND_STEPS`) to darkened and tinted blue for the night, tinted icy blue while the
+    // enemies are frozen, and under rain further darkened by `darken_percent`
+    pub fn create_shaded_palettes(
+        night_step: u16,
+        is_frozen: bool,
+        darken_percent: u16,
+    ) -> Vec<Palette16> {
+        BG_PALETTES
+            .iter()
+            .map(|palette| {
+                let mut shaded = palette.clone();
+                for index in 0..16 {
+                    let day = palette.colour(index);
+                    let colour =
+                        blend_colour(day, night_colour(day), night_step, NIGHT_BLEND_STEPS);
+                    let colour = if is_frozen {
+                        frozen_colour(colour)
+                    } else {
+                        colour
+                    };
+                    shaded.update_colour(index, darken_colour(colour, darken_percent));
+                }
+                shaded
+            })
+            .collect()
+    }
+    fn darken_colour(colour: u16, percent: u16) -> u16 {
+        let scale = |channel: u16| channel * (100 - percent) / 100;
+        scale(colour & 0x1f)
+            | (scale((colour >> 5) & 0x1f) << 5)
+            | (scale((colour >> 10) & 0x1f) << 10)
+    }
+    // `step / steps` of the way from `from` to `to`, channel by channel
+    fn blend_colour(from: u16, to: u16, step: u16, steps: u16) -> u16 {
+        let blend = |shift: u16| {
+            let a = ((from >> shift) & 0x1f) as i32;
+            let b = ((to >> shift) & 0x1f) as i32;
+            ((a + (b - a) * step as i32 / steps as i32) as u16) << shift
+        };
+        blend(0) | blend(5) | blend(10)
+    }
+    fn night_colour(colour: u16) -> u16 {
+        let r = colour & 0x1f;
+        let g = (colour >> 5) & 0x1f;
+        let b = (colour >> 10) & 0x1f;
+        (r * 3 / 10) | ((g * 4 / 10) << 5) | ((b * 6 / 10 + 4).min(0x1f) << 10)
+    }
+    fn frozen_colour(colour: u16) -> u16 {
+        let r = colour & 0x1f;
+        let g = (colour >> 5) & 0x1f;
+        let b = (colour >> 10) & 0x1f;
+        (r * 6 / 10) | ((g * 8 / 10 + 2) << 5) | ((b + 8).min(0x1f) << 10)
+    }
+
+    fn dino_tag(skin: Skin) -> &'static Tag {
+        match skin {
+            Skin::Default => DINO,
+            Skin::Red => DINO_RED,
+        }
+    }
+    // The last frame of a dino tag is the game over pose and the rest are the run cycle
+    pub(super) fn dino_run_sprites(skin: Skin) -> &'static [Sprite] {
+        let sprites = dino_tag(skin).sprites();
+        &sprites[..sprites.len() - 1]
+    }
+    pub(super) fn dino_duck_sprites(skin: Skin) -> &'static [Sprite] {
+        match skin {
+            Skin::Default => DINO_DUCK.sprites(),
+            Skin::Red => DINO_RED_DUCK.sprites(),
+        }
+    }
+    pub(super) fn dino_over_sprite(skin: Skin) -> &'static Sprite {
+        dino_tag(skin).sprites().last().unwrap()
+    }
+    // Run frame reused as the jump pose
+    pub(super) const DINO_JUMP_FRAME: usize = 1;
 
     pub fn create_tile_map() -> Vec<usize> {
         TILE_MAP_CSV_STR
             .split([',', '\r', '\n'])
-            .map(|s| usize::from_str_radix(s, 10).unwrap_or(0))
+            .map(|s| s.parse::<usize>().unwrap_or(0))
             .collect()
     }
-    pub(super) fn create_char_sprite_map() -> HashMap<char, &'static Sprite> {
+    pub(super) fn create_char_sprite_map(keys: &[&str]) -> HashMap<char, &'static Sprite> {
         let mut map: HashMap<char, &'static Sprite> = HashMap::new();
-        for sprite_key in CHAR_SPRITE_KEYS {
+        for sprite_key in keys {
             let sprite = FONT_SPRITES.tags().get(sprite_key).sprite(0);
             map.insert(sprite_key.chars().next().unwrap(), sprite);
         }
         map
     }
 
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
     pub(super) enum SoundEffectKind {
         Jump,
         Over,
         Up,
+        Spring,
+        Coin,
+        Fanfare,
+        ExtraLife,
+        Knockback,
+        NearMiss,
+    }
+
+    impl SoundEffectKind {
+        pub(super) const ALL: [SoundEffectKind; 9] = [
+            SoundEffectKind::Jump,
+            SoundEffectKind::Over,
+            SoundEffectKind::Up,
+            SoundEffectKind::Spring,
+            SoundEffectKind::Coin,
+            SoundEffectKind::Fanfare,
+            SoundEffectKind::ExtraLife,
+            SoundEffectKind::Knockback,
+            SoundEffectKind::NearMiss,
+        ];
+
+        pub(super) fn name(self) -> &'static str {
+            match self {
+                SoundEffectKind::Jump => "JUMP",
+                SoundEffectKind::Over => "GAME OVER",
+                SoundEffectKind::Up => "LEVEL UP",
+                SoundEffectKind::Spring => "SPRING",
+                SoundEffectKind::Coin => "COIN",
+                SoundEffectKind::Fanfare => "FANFARE",
+                SoundEffectKind::ExtraLife => "EXTRA LIFE",
+                SoundEffectKind::Knockback => "KNOCKBACK",
+                SoundEffectKind::NearMiss => "NEAR MISS",
+            }
+        }
     }
     pub(super) const JUMP_SOUND: &[u8] = include_bytes!("../assets/sfx/jump.raw"); // include_wav!("assets/sfx/jump.wav");
     pub(super) const OVER_SOUND: &[u8] = include_bytes!("../assets/sfx/over.raw");
     pub(super) const UP_SOUND: &[u8] = include_bytes!("../assets/sfx/up.raw");
+    pub(super) const SPRING_SOUND: &[u8] = include_bytes!("../assets/sfx/spring.raw");
+    pub(super) const COIN_SOUND: &[u8] = include_bytes!("../assets/sfx/coin.raw");
+    pub(super) const FANFARE_SOUND: &[u8] = include_bytes!("../assets/sfx/fanfare.raw");
+    pub(super) const LIFE_SOUND: &[u8] = include_bytes!("../assets/sfx/life.raw");
+    pub(super) const KNOCK_SOUND: &[u8] = include_bytes!("../assets/sfx/knock.raw");
+    pub(super) const NEAR_MISS_SOUND: &[u8] = include_bytes!("../assets/sfx/near_miss.raw");
+    // Loops seamlessly, see `get_whoosh_sound`
+    const WHOOSH_SOUND: &[u8] = include_bytes!("../assets/sfx/whoosh.raw");
+
+    // The raw samples above are all recorded at this rate
+    const SAMPLE_RATE_HZ: u32 = 10512;
+    // The `hq_audio` feature mixes at a higher rate, which costs CPU time every frame but
+    // aliases less. agb keeps the rate to itself, so it's repeated here.
+    #[cfg(not(feature = "hq_audio"))]
+    pub const MIXER_FREQUENCY: Frequency = Frequency::Hz10512;
+    #[cfg(not(feature = "hq_audio"))]
+    const MIXER_RATE_HZ: u32 = 10512;
+    #[cfg(feature = "hq_audio")]
+    pub const MIXER_FREQUENCY: Frequency = Frequency::Hz18157;
+    #[cfg(feature = "hq_audio")]
+    const MIXER_RATE_HZ: u32 = 18157;
+    // Steps through the samples slower than one per output sample at higher mixer rates, so
+    // they keep their pitch. Exactly 1 at the default rate, and a mixer slower than the samples
+    // would skip over them.
+    const SAMPLE_PLAYBACK: Num<u32, 8> = Num::from_raw((SAMPLE_RATE_HZ << 8) / MIXER_RATE_HZ);
 
     pub(super) fn get_sound(kind: SoundEffectKind) -> SoundChannel {
         let data: &'static [u8] = match kind {
             SoundEffectKind::Jump => JUMP_SOUND,
             SoundEffectKind::Over => OVER_SOUND,
             SoundEffectKind::Up => UP_SOUND,
+            SoundEffectKind::Spring => SPRING_SOUND,
+            SoundEffectKind::Coin => COIN_SOUND,
+            SoundEffectKind::Fanfare => FANFARE_SOUND,
+            SoundEffectKind::ExtraLife => LIFE_SOUND,
+            SoundEffectKind::Knockback => KNOCK_SOUND,
+            SoundEffectKind::NearMiss => NEAR_MISS_SOUND,
         };
-        SoundChannel::new(data)
+        let mut channel = SoundChannel::new(data);
+        channel.playback(SAMPLE_PLAYBACK);
+        channel
+    }
+
+    // High priority so the sound effects can't take its channel, and it plays until stopped
+    pub(super) fn get_whoosh_sound() -> SoundChannel {
+        let mut channel = SoundChannel::new_high_priority(WHOOSH_SOUND);
+        channel.should_loop().playback(SAMPLE_PLAYBACK).volume(0);
+        channel
     }
 
+    // OBJ VRAM is 32KiB in the tiled modes, i.e. 1024 4bpp tiles
+    pub const OBJ_VRAM_TILES: usize = 1024;
+
     pub const DINO_COLLISION_RECT: Rect<u16> = Rect::<u16> {
         position: Vector2D::new(9, 4),
         size: Vector2D::new(18, 27),
     };
+    // Low enough that a mid bird's box (y 86..91) passes over it, a ground bird's still hits
+    pub const DINO_DUCK_COLLISION_RECT: Rect<u16> = Rect::<u16> {
+        position: Vector2D::new(9, 14),
+        size: Vector2D::new(18, 17),
+    };
+    // The body only, as the wings sweep well above and below it, and trimmed by this much more
+    // at the top and bottom so clipping a feather isn't a hit. The width is left as it is. The
+    // box is relative to the sprite, so it bobs along with a bobbing bird.
+    pub const BIRD_VERTICAL_TOLERANCE_PX: u16 = 1;
     pub const BIRD_COLLISION_RECT: Rect<u16> = Rect::<u16> {
-        position: Vector2D::new(1, 13),
-        size: Vector2D::new(28, 7),
+        position: Vector2D::new(1, 13 + BIRD_VERTICAL_TOLERANCE_PX),
+        size: Vector2D::new(28, 7 - BIRD_VERTICAL_TOLERANCE_PX * 2),
     };
     pub const CACTUS_COLLISION_RECT: Rect<u16> = Rect::<u16> {
         position: Vector2D::new(1, 6),
         size: Vector2D::new(27, 25),
     };
-    // pub const BG_TILES_WIDTH: u16 = 64;
+    pub const CACTUS_SMALL_COLLISION_RECT: Rect<u16> = Rect::<u16> {
+        position: Vector2D::new(9, 19),
+        size: Vector2D::new(9, 13),
+    };
+    // Only clears with the dino near the top of its jump
+    pub const CACTUS_TALL_COLLISION_RECT: Rect<u16> = Rect::<u16> {
+        position: Vector2D::new(6, 1),
+        size: Vector2D::new(20, 30),
+    };
+    pub const SPRING_COLLISION_RECT: Rect<u16> = Rect::<u16> {
+        position: Vector2D::new(9, 19),
+        size: Vector2D::new(14, 12),
+    };
+    // Low enough that any jump clears it, but as wide as a cactus
+    pub const SPIKE_COLLISION_RECT: Rect<u16> = Rect::<u16> {
+        position: Vector2D::new(3, 26),
+        size: Vector2D::new(26, 6),
+    };
+    pub const COIN_COLLISION_RECT: Rect<u16> = Rect::<u16> {
+        position: Vector2D::new(1, 0),
+        size: Vector2D::new(6, 8),
+    };
+    // The CSV is `BG_TILES_WIDTH` x `BG_TILES_HEIGHT`, centred in a screen's height of rows
+    pub const BG_TILES_WIDTH: u16 = 64;
     pub const BG_TILES_HEIGHT: u16 = 14;
-    pub const BG_TILES_OFFSET_Y: u16 = (20 - BG_TILES_HEIGHT) / 2;
+    pub const SCREEN_TILES_HEIGHT: u16 = 20;
+    pub const BG_TILES_OFFSET_Y: u16 = (SCREEN_TILES_HEIGHT - BG_TILES_HEIGHT) / 2;
     pub const BG_BLANK_TILE_IDX: u16 = 1;
     pub const GROUND_TILE_Y: u16 = 11 + BG_TILES_OFFSET_Y;
+    // Ground top tiles with a rock or grass tuft on them, placed over the map's own ground top
+    // row. They are plain background tiles, so they scroll with the ground and never collide,
+    // and are kept a few pixels tall so they can't be mistaken for cactuses.
+    pub const GROUND_TOP_MAP_Y: u16 = 11;
+    const GROUND_DETAIL_ROCK_IDX: u16 = 24;
+    const GROUND_DETAIL_TUFT_IDX: u16 = 25;
+
+    // Picks details from a hash of the absolute tile column so they don't repeat with the map
+    pub fn ground_detail_tile(x: i32) -> Option<u16> {
+        match (x as u32).wrapping_mul(0x9E37_79B1) >> 27 {
+            0 => Some(GROUND_DETAIL_ROCK_IDX),
+            1 => Some(GROUND_DETAIL_TUFT_IDX),
+            _ => None,
+        }
+    }
+    // Tile at `pos` on the scrolling background, from the map given by `create_tile_map`. It
+    // repeats every `BG_TILES_WIDTH` columns and `SCREEN_TILES_HEIGHT` rows, blank above and
+    // below the CSV's band, with the ground top row's details on top.
+    pub fn background_tile(tile_map: &[usize], pos: Vector2D<i32>) -> usize {
+        let x = pos.x.rem_euclid(BG_TILES_WIDTH as i32) as u16;
+        let y = pos.y.rem_euclid(SCREEN_TILES_HEIGHT as i32) as u16;
+        let Some(map_y) = y
+            .checked_sub(BG_TILES_OFFSET_Y)
+            .filter(|&map_y| map_y < BG_TILES_HEIGHT)
+        else {
+            return BG_BLANK_TILE_IDX as usize;
+        };
+        match ground_detail_tile(pos.x) {
+            Some(detail_idx) if map_y == GROUND_TOP_MAP_Y => detail_idx as usize,
+            _ => *tile_map
+                .get((x + BG_TILES_WIDTH * map_y) as usize)
+                .unwrap_or(&(BG_BLANK_TILE_IDX as usize)),
+        }
+    }
+
+    // Where the map draws the ground; `Settings::ground_y` can move it, and everything below is
+    // laid out for this line and shifted along with it
     pub const GROUND_Y: u16 = GROUND_TILE_Y * 8 + 2;
 
     pub const DINO_GROUNDED_Y: u16 = GROUND_Y - 32;
     pub const CACTUS_Y: u16 = GROUND_Y - 32;
+    pub const SPRING_Y: u16 = GROUND_Y - 32;
+    pub const SPIKE_Y: u16 = GROUND_Y - 32;
+
+    // Heights a bird can fly at, each asking for a different response. Against the standing
+    // dino's box (y 86..113) the bird's box (y + 13 + `BIRD_VERTICAL_TOLERANCE_PX`, 5px tall)
+    // lands:
+    // - Ground, y 88: across its legs, jump over it
+    // - Mid, y 72: across its head, duck under it or clear it with a full jump
+    // - High, y 56: 11px over its head, keep running and don't jump into it
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum BirdBand {
+        Ground,
+        Mid,
+        High,
+    }
+
+    impl BirdBand {
+        pub fn y(self) -> i32 {
+            match self {
+                BirdBand::Ground => 88,
+                BirdBand::Mid => 72,
+                BirdBand::High => 56,
+            }
+        }
+    }
 }
 
 use crate::{
     game::resource::{
-        create_char_sprite_map, BIRD_COLLISION_RECT, CACTUS_COLLISION_RECT, DINO_COLLISION_RECT,
-        NUMBER,
+        create_char_sprite_map, BIRD_COLLISION_RECT, CACTUS_COLLISION_RECT, CACTUS_SMALL,
+        CACTUS_SMALL_COLLISION_RECT, CACTUS_TALL, CACTUS_TALL_COLLISION_RECT, CHAR_SPRITE_KEYS,
+        COIN, COIN_COLLISION_RECT, CONFETTI, DINO_COLLISION_RECT, DINO_DUCK,
+        DINO_DUCK_COLLISION_RECT, DINO_HIT, DUST, FEATHER, HEART, MAGNET, MOON, MOON_POSITION,
+        NUMBER, OBJ_VRAM_TILES, OVERLAY_CHAR_SPRITE_KEYS, SNOWFLAKE, SPIKE, SPIKE_COLLISION_RECT,
+        SPIKE_Y, SPRING, SPRING_COLLISION_RECT, SPRING_Y, STAR, STAR_POSITIONS, WARNING,
     },
+    rumble::Rumble,
+    save::{GameSnapshot, Preference, SaveBuffer, SnapshotReader, Unlock},
     utils::print_info,
 };
 
 use self::resource::{
-    get_sound, SoundEffectKind, BG_TILES_OFFSET_Y, BIRD, CACTUS, CACTUS_Y, DINO, DINO_GROUNDED_Y,
+    create_shaded_palettes, dino_duck_sprites, dino_over_sprite, dino_run_sprites, get_sound,
+    get_whoosh_sound, BirdBand, SoundEffectKind, BG_TILES_OFFSET_Y, BIRD, CACTUS, CACTUS_Y, DINO,
+    DINO_GROUNDED_Y, DINO_JUMP_FRAME, DINO_RED, DINO_RED_DUCK, GROUND_Y, NIGHT_BLEND_STEPS,
+    RAIN_TILES_DATA, RAIN_TILE_COUNT,
 };
 
+// Frame counts come from the slices, i.e. from the aseprite tags, so nothing else has to change
+// when an animation gains frames
+fn generate_sprites(sprites: &'static [Sprite], loader: &mut SpriteLoader) -> Box<[SpriteVram]> {
+    sprites
+        .iter()
+        .map(|x| loader.get_vram_sprite(x))
+        .collect::<Vec<_>>()
+        .into_boxed_slice()
+}
+
+fn load_char_sprites(keys: &[&str], loader: &mut SpriteLoader) -> HashMap<char, SpriteVram> {
+    let mut char_sprite_vram_map: HashMap<char, SpriteVram> = HashMap::new();
+    for (key, sprite) in create_char_sprite_map(keys).iter() {
+        char_sprite_vram_map.insert(*key, loader.get_vram_sprite(sprite));
+    }
+    char_sprite_vram_map
+}
+
 #[derive(Clone)]
-pub struct SpriteWithCollisionRect {
-    sprite: SpriteVram,
-    rect: Rect<u16>,
+struct OverlaySprites {
+    dino_over: SpriteVram,
+    char_map: HashMap<char, SpriteVram>,
 }
 
 #[derive(Clone)]
 pub struct SpriteCache {
-    dino: Box<[SpriteWithCollisionRect]>,
-    bird: Box<[SpriteWithCollisionRect]>,
-    cactus: SpriteWithCollisionRect,
+    dino: Box<[SpriteVram]>,
+    dino_hit: Box<[SpriteVram]>,
+    dino_duck: Box<[SpriteVram]>,
+    bird: Box<[SpriteVram]>,
+    cactus: Box<[SpriteVram]>,
+    cactus_small: Box<[SpriteVram]>,
+    cactus_tall: Box<[SpriteVram]>,
+    spring: Box<[SpriteVram]>,
+    spike: SpriteVram,
+    coin: Box<[SpriteVram]>,
+    magnet: SpriteVram,
+    feather: SpriteVram,
+    snowflake: SpriteVram,
+    heart: SpriteVram,
+    warning: SpriteVram,
     numbers: Box<[SpriteVram]>,
+    dust: Box<[SpriteVram]>,
+    confetti: Box<[SpriteVram]>,
+    moon: SpriteVram,
+    stars: Box<[SpriteVram]>,
     char_map: HashMap<char, SpriteVram>,
+    overlay: Option<OverlaySprites>,
+    skin: Skin,
 }
 
 impl SpriteCache {
     pub fn new(loader: &mut SpriteLoader) -> Self {
-        fn generate_sprites(
-            tag: &'static Tag,
-            range: Range<usize>,
-            loader: &mut SpriteLoader,
-        ) -> Box<[SpriteVram]> {
-            range
-                .map(|x| tag.sprite(x))
-                .map(|x| loader.get_vram_sprite(x))
-                .collect::<Vec<_>>()
-                .into_boxed_slice()
-        }
-        fn generate_sprites_with_collision_rect(
-            tag: &'static Tag,
-            range: Range<usize>,
-            loader: &mut SpriteLoader,
-            collision_rect: Rect<u16>,
-        ) -> Box<[SpriteWithCollisionRect]> {
-            range
-                .map(|x| tag.sprite(x))
-                .map(|x| SpriteWithCollisionRect {
-                    sprite: loader.get_vram_sprite(x),
-                    rect: collision_rect.clone(),
-                })
-                .collect::<Vec<_>>()
-                .into_boxed_slice()
-        }
-
-        let mut char_sprite_vram_map: HashMap<char, SpriteVram> = HashMap::new();
-        let char_sprite_map = create_char_sprite_map();
-        for (key, sprite) in char_sprite_map.iter() {
-            char_sprite_vram_map.insert(*key, loader.get_vram_sprite(sprite));
-        }
+        // Gameplay sprites are loaded here so nothing gets uploaded mid-run, and the overlay
+        // set is loaded on top of them by `set_overlay_loaded`, so both have to fit in OBJ VRAM.
+        // Every skin is counted, so whichever one `set_skin` swaps in fits.
+        let footprint_tiles: usize = [
+            DINO,
+            DINO_RED,
+            DINO_HIT,
+            DINO_DUCK,
+            DINO_RED_DUCK,
+            BIRD,
+            SPRING,
+            NUMBER,
+            DUST,
+            STAR,
+            COIN,
+            CONFETTI,
+            CACTUS,
+            CACTUS_SMALL,
+            CACTUS_TALL,
+        ]
+        .iter()
+        .flat_map(|tag| tag.sprites())
+        .chain([MOON, SPIKE, MAGNET, FEATHER, SNOWFLAKE, HEART, WARNING])
+        .chain(create_char_sprite_map(&CHAR_SPRITE_KEYS).values().copied())
+        .chain(
+            create_char_sprite_map(&OVERLAY_CHAR_SPRITE_KEYS)
+                .values()
+                .copied(),
+        )
+        .map(|sprite| {
+            let (width, height) = sprite.size().to_tiles_width_height();
+            width * height
+        })
+        .sum();
+        assert!(
+            footprint_tiles <= OBJ_VRAM_TILES,
+            "sprites need {} tiles but OBJ VRAM only has {}",
+            footprint_tiles,
+            OBJ_VRAM_TILES
+        );
 
         Self {
-            dino: generate_sprites_with_collision_rect(DINO, 0..3, loader, DINO_COLLISION_RECT),
-            bird: generate_sprites_with_collision_rect(BIRD, 0..2, loader, BIRD_COLLISION_RECT),
-            cactus: SpriteWithCollisionRect {
-                sprite: loader.get_vram_sprite(CACTUS),
-                rect: CACTUS_COLLISION_RECT,
-            },
-            numbers: generate_sprites(NUMBER, 0..10, loader),
-            char_map: char_sprite_vram_map,
+            dino: generate_sprites(dino_run_sprites(Skin::Default), loader),
+            dino_hit: generate_sprites(DINO_HIT.sprites(), loader),
+            dino_duck: generate_sprites(dino_duck_sprites(Skin::Default), loader),
+            bird: generate_sprites(BIRD.sprites(), loader),
+            cactus: generate_sprites(CACTUS.sprites(), loader),
+            cactus_small: generate_sprites(CACTUS_SMALL.sprites(), loader),
+            cactus_tall: generate_sprites(CACTUS_TALL.sprites(), loader),
+            spring: generate_sprites(SPRING.sprites(), loader),
+            spike: loader.get_vram_sprite(SPIKE),
+            coin: generate_sprites(COIN.sprites(), loader),
+            magnet: loader.get_vram_sprite(MAGNET),
+            feather: loader.get_vram_sprite(FEATHER),
+            snowflake: loader.get_vram_sprite(SNOWFLAKE),
+            heart: loader.get_vram_sprite(HEART),
+            warning: loader.get_vram_sprite(WARNING),
+            numbers: generate_sprites(NUMBER.sprites(), loader),
+            dust: generate_sprites(DUST.sprites(), loader),
+            confetti: generate_sprites(CONFETTI.sprites(), loader),
+            moon: loader.get_vram_sprite(MOON),
+            stars: generate_sprites(STAR.sprites(), loader),
+            char_map: load_char_sprites(&CHAR_SPRITE_KEYS, loader),
+            overlay: None,
+            skin: Skin::Default,
+        }
+    }
+
+    // Swaps the dino frames for the given skin, called between runs
+    pub fn set_skin(&mut self, loader: &mut SpriteLoader, skin: Skin) {
+        if self.skin == skin {
+            return;
         }
+        // The old frames are let go first, so only whatever OAM still shows from the last
+        // frame is held alongside the new ones
+        self.dino = Box::default();
+        self.dino_duck = Box::default();
+        self.overlay = None;
+        self.dino = generate_sprites(dino_run_sprites(skin), loader);
+        self.dino_duck = generate_sprites(dino_duck_sprites(skin), loader);
+        self.skin = skin;
+    }
+
+    // Keeps the overlay graphics (pause/game over screens and menus) in VRAM only while
+    // something needs them, see `Game::needs_overlay`. Releasing drops the last `SpriteVram`
+    // references, so render must not assume they exist.
+    pub fn set_overlay_loaded(&mut self, loader: &mut SpriteLoader, loaded: bool) {
+        if !loaded {
+            self.overlay = None;
+        } else if self.overlay.is_none() {
+            self.overlay = Some(OverlaySprites {
+                dino_over: loader.get_vram_sprite(dino_over_sprite(self.skin)),
+                char_map: load_char_sprites(&OVERLAY_CHAR_SPRITE_KEYS, loader),
+            });
+        }
+    }
+
+    fn char_sprite(&self, char: char) -> Option<&SpriteVram> {
+        // Digits are shared with the score
+        if let Some(digit) = char.to_digit(10) {
+            return self.numbers.get(digit as usize);
+        }
+        self.char_map.get(&char).or_else(|| {
+            self.overlay
+                .as_ref()
+                .and_then(|overlay| overlay.char_map.get(&char))
+        })
     }
 }
 
@@ -183,33 +598,381 @@ struct Player {
     vertical_speed: Number,
 
     is_jumping: bool,
+    landing_frames: u16,
+    // Sideways speed of a knockback, kept until the dino lands
+    knockback_speed: Number,
+    // Only on the ground, and only while DOWN or B is held
+    is_ducking: bool,
+    // Jumped of its own accord rather than thrown by a spring or a hit, and not cut short yet
+    can_cut_jump: bool,
+}
+
+impl Player {
+    fn collision_rect(&self) -> Rect<u16> {
+        if self.is_ducking {
+            DINO_DUCK_COLLISION_RECT
+        } else {
+            DINO_COLLISION_RECT
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Weather {
+    Clear,
+    RainStarting,
+    Raining,
+    RainEnding,
+}
+
+#[derive(Debug)]
+enum ParticleKind {
+    Dust,
+    // Trails the dino while low gravity lasts
+    Sparkle,
+    // Blows across the screen around a gust
+    Gust,
+    // Rains down while a new hi score is celebrated, in one of the confetti colours
+    Confetti { colour: usize },
 }
 
+struct Particle {
+    kind: ParticleKind,
+    position: Vector2D<Number>,
+    velocity: Vector2D<Number>,
+    frames_left: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CollectibleKind {
+    Coin,
+    Magnet,
+    Feather,
+    Snowflake,
+}
 #[derive(Debug)]
-enum EnemyKind {
+struct Collectible {
+    kind: CollectibleKind,
+    position: Vector2D<Number>,
+    // Speed towards the dino while a magnet is pulling it
+    pull_speed: Number,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CactusSize {
+    Small,
+    Normal,
+    Tall,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnemyKind {
     Bird,
-    Cactus,
+    Cactus(CactusSize),
+    Spring,
+    // Flat along the ground, has to be jumped like a cactus
+    Spike,
+}
+
+impl EnemyKind {
+    fn collision_rect(&self) -> Rect<u16> {
+        match self {
+            EnemyKind::Bird => BIRD_COLLISION_RECT,
+            EnemyKind::Cactus(CactusSize::Small) => CACTUS_SMALL_COLLISION_RECT,
+            EnemyKind::Cactus(CactusSize::Normal) => CACTUS_COLLISION_RECT,
+            EnemyKind::Cactus(CactusSize::Tall) => CACTUS_TALL_COLLISION_RECT,
+            EnemyKind::Spring => SPRING_COLLISION_RECT,
+            EnemyKind::Spike => SPIKE_COLLISION_RECT,
+        }
+    }
+
+    fn width(&self) -> i32 {
+        let sprite = match self {
+            EnemyKind::Bird => BIRD.sprite(0),
+            EnemyKind::Cactus(CactusSize::Small) => CACTUS_SMALL.sprite(0),
+            EnemyKind::Cactus(CactusSize::Normal) => CACTUS.sprite(0),
+            EnemyKind::Cactus(CactusSize::Tall) => CACTUS_TALL.sprite(0),
+            EnemyKind::Spring => SPRING.sprite(0),
+            EnemyKind::Spike => SPIKE,
+        };
+        sprite.size().to_width_height().0 as i32
+    }
 }
+
 #[derive(Debug)]
 struct Enemy {
     kind: EnemyKind,
     position: Vector2D<Number>,
+    // Springs only fire once
+    triggered: bool,
+    is_fast: bool,
+    // Came within `NEAR_MISS_MARGIN_PX` of the dino without touching it
+    grazed: bool,
+    // Offsets the cactus sway so neighbours don't move in lockstep
+    sway_phase: u32,
+    // Frame the enemy spawned on, which a bobbing bird's bob starts level from
+    spawn_frame: u32,
+    // Height the enemy spawned at, which a bobbing bird is centred on
+    spawn_y: Number,
+}
+
+// One step of the run as it began, kept in debug builds to look back on after a hit
+#[cfg(debug_assertions)]
+struct TraceFrame {
+    frame: u32,
+    input: FrameInput,
+    player: Vector2D<Number>,
+    // The leftmost few, which are the ones that can reach the dino
+    enemies: [Option<(EnemyKind, Vector2D<Number>)>; TRACE_ENEMIES],
+}
+
+// A change of state and the step it happened on, logged in debug builds like the trace
+#[cfg(debug_assertions)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StateTransition {
+    pub frame: u32,
+    pub from: GameState,
+    pub to: GameState,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Skin {
+    Default,
+    Red,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "EASY",
+            Difficulty::Normal => "NORMAL",
+            Difficulty::Hard => "HARD",
+        }
+    }
+    pub fn init_scroll_velocity(self) -> Number {
+        match self {
+            Difficulty::Easy => num!(2.8),
+            Difficulty::Normal => num!(3.4),
+            Difficulty::Hard => num!(4.0),
+        }
+    }
+    pub fn spawn_delay_factor(self) -> Number {
+        match self {
+            Difficulty::Easy => num!(1.25),
+            Difficulty::Normal => num!(1.0),
+            Difficulty::Hard => num!(0.8),
+        }
+    }
+    pub fn scroll_velocity_increase_per_level(self) -> Number {
+        match self {
+            Difficulty::Easy => num!(0.1),
+            Difficulty::Normal => num!(0.15),
+            Difficulty::Hard => num!(0.2),
+        }
+    }
+    fn harder(self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            _ => Difficulty::Hard,
+        }
+    }
+    fn easier(self) -> Self {
+        match self {
+            Difficulty::Hard => Difficulty::Normal,
+            _ => Difficulty::Easy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Achievement {
+    Level10,
+    Coins1000,
+    Survive5Minutes,
+    NearMisses10,
+}
+
+impl Achievement {
+    pub const ALL: [Achievement; 4] = [
+        Achievement::Level10,
+        Achievement::Coins1000,
+        Achievement::Survive5Minutes,
+        Achievement::NearMisses10,
+    ];
+
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Achievement::Level10 => "REACH LEVEL 10",
+            Achievement::Coins1000 => "HOLD 1000 COINS",
+            Achievement::Survive5Minutes => "SURVIVE 5:00",
+            Achievement::NearMisses10 => "10 NEAR MISSES",
+        }
+    }
 }
 
+// Calm runs level up this many times at most
+const CALM_MAX_SPEED_LEVEL: u16 = 2;
+const CALM_MAX_ENEMIES: usize = 2;
+const CALM_EXTRA_SPAWN_GAP_PX: i32 = 48;
+
 #[derive(Clone, Copy, Debug)]
 pub struct Settings {
     pub init_scroll_velocity: Number,
 
     pub scroll_velocity_increase_per_level: Number,
     pub frames_to_level_up: u32,
+    // The pace stops picking up once it's reached this level, `u16::MAX` for no cap
+    pub max_speed_level: u16,
+    // Frames survived for each point of score, on top of the coin bonuses
+    pub frames_per_point: u32,
+    // Counts frames by the ground scrolled instead, as if at `init_scroll_velocity`, so the
+    // faster levels score faster
+    pub speed_weighted_score: bool,
 
     pub animation_interval_frames: u16,
     pub spawn_interval_frames: u16,
+    // Scales the time between spawns, above 1 for looser spacing and below for tighter
+    pub spawn_delay_factor: Number,
+    pub coin_spawn_interval_frames: u32,
     pub jump_height_px: u16,
+    // Frames up to the top of a jump, and back down from it. Equal for the same gravity both
+    // ways, a shorter fall for one that drops faster than it rises.
     pub jump_duration_frames: u16,
+    pub fall_duration_frames: u16,
+    // Upward speed kept when the jump button is let go on the way up, 1 for every jump the
+    // same height
+    pub min_jump_cut_multiplier: Number,
+    // Frames after landing before the dino can jump again, 0 for straight away
+    pub jump_cooldown_frames: u32,
     pub max_enemies_displayed: usize,
+    pub show_time: bool,
+    // Accessibility: turns off effects that can be uncomfortable to watch, like slow motion
+    pub reduced_flashing: bool,
+    // How long a warning is shown at the right edge before a fast enemy enters
+    pub fast_enemy_warning_frames: u32,
+    // Hits survived before the game is over
+    pub extra_lives: u8,
+    // Score between lives earned along the way, 0 for none
+    pub extra_life_interval: u32,
+    // Highest multiplier a streak of dodges can build up to, 1 for no combos
+    pub max_combo: u32,
+    // Enemy kinds that may spawn, e.g. to bring birds in only after a tutorial's cactuses
+    pub enable_birds: bool,
+    pub enable_cactus: bool,
+    // Birds bob up and down on a sine wave, hitbox and all, instead of flying level
+    pub bird_bob: bool,
+    // Occasional wind gusts that push the dino while it's in the air
+    pub weather: bool,
+    // Players taking turns, one run each
+    pub players: u8,
+    // Pulses the cartridge's rumble motor, where there is one, on hits and level ups
+    pub rumble: bool,
+    // Restarting from game over takes a second press, so a stray one can't skip the score
+    pub confirm_restart: bool,
+    // Holding A this long on game over restarts without the second press, 0 to turn it off
+    pub restart_hold_frames: u32,
+    // Unpausing counts down from 3 before the run carries on, rather than straight away
+    pub resume_countdown: bool,
+    // Scripted on-ramp for new players, see `TUTORIAL`
+    pub tutorial: bool,
+    // Off for runs kept off the scoreboard, like the tutorial and calm ones, which don't earn
+    // achievements either
+    pub is_ranked: bool,
+    // Ground left between enemies on top of what a jump needs
+    pub extra_spawn_gap_px: i32,
+    // Trimmed off every side of the dino's and the hazards' boxes before they're tested, so a
+    // pass that only just touches isn't a hit. 0 for exact collision.
+    pub collision_grace_px: u16,
+    // Screen row of the ground line, `GROUND_Y` for the standard map's horizon
+    pub ground_y: u16,
+    pub hud: HudLayout,
+
+    // Each cycle is `day_frames` of day followed by `night_frames` of night
+    pub day_frames: u32,
+    pub night_frames: u32,
+    // Frames the background takes to fade into and back out of the night, 0 for a hard cut.
+    // Both fades happen within the night.
+    pub night_transition_frames: u32,
 
     pub hi_score: u32,
+    // Final score of the run before this one, 0 if there wasn't one
+    pub last_score: u32,
+    // Everything random in a run comes from this, so equal seeds and inputs give equal runs
+    pub rng_seed: [u32; 4],
+
+    // Progress carried over from the save, for achievements
+    pub achievements: u8,
+    pub coin_balance: u32,
+}
+
+impl Settings {
+    // A long relaxed run that stays gentle however long it lasts: the easy pace, capped after a
+    // couple of levels, no birds and plenty of ground between fewer enemies
+    pub fn calm(self) -> Self {
+        Self {
+            init_scroll_velocity: Difficulty::Easy.init_scroll_velocity(),
+            scroll_velocity_increase_per_level: Difficulty::Easy
+                .scroll_velocity_increase_per_level(),
+            max_speed_level: CALM_MAX_SPEED_LEVEL,
+            spawn_delay_factor: Difficulty::Easy.spawn_delay_factor() + num!(0.5),
+            extra_spawn_gap_px: CALM_EXTRA_SPAWN_GAP_PX,
+            max_enemies_displayed: CALM_MAX_ENEMIES,
+            enable_birds: false,
+            is_ranked: false,
+            ..self
+        }
+    }
+
+    // How far the ground sits below where the map was drawn for
+    fn ground_offset(&self) -> i32 {
+        self.ground_y as i32 - GROUND_Y as i32
+    }
+}
+
+// Where the in-game HUD goes. Scores are anchored at the right edge of their digits with the
+// label to their left, everything else at its top left.
+#[derive(Clone, Copy, Debug)]
+pub struct HudLayout {
+    pub score: Vector2D<i32>,
+    pub hi_score: Vector2D<i32>,
+    // Only shown on the game over screen
+    pub last_score: Vector2D<i32>,
+    pub time: Vector2D<i32>,
+    pub distance: Vector2D<i32>,
+    pub lives: Vector2D<i32>,
+    // The first power-up's icon, the second goes 24px to its right
+    pub power_ups: Vector2D<i32>,
+    // Only shown during a run while there's a combo going
+    pub combo: Vector2D<i32>,
+}
+
+impl Default for HudLayout {
+    // Two rows in the sky above the map: hi score, lives and power-ups on top, then score and
+    // time
+    fn default() -> Self {
+        let top = (BG_TILES_OFFSET_Y * 8 - 18) as i32;
+        let second = top + 9;
+        Self {
+            score: (236, second).into(),
+            hi_score: (236, top).into(),
+            last_score: (236, second + 9).into(),
+            time: (4, second).into(),
+            distance: (4, second + 9).into(),
+            lives: (52, top).into(),
+            power_ups: (4, top).into(),
+            combo: (236, second + 9).into(),
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -218,10 +981,15 @@ pub enum GameState {
     Pause,
     Over(u32),
     Restart,
+    Shop,
+    // Abandoned mid-run, back to the title without banking anything
+    Quit,
+    // The options menu over a paused run, see `resume_from_options`
+    Options,
 }
 
 #[derive(Clone, Copy, Debug)]
-struct SpawnInfo(u8);
+pub struct SpawnInfo(u8);
 impl From<u8> for SpawnInfo {
     fn from(value: u8) -> Self {
         Self(value)
@@ -233,16 +1001,41 @@ impl SpawnInfo {
         (self.0 & 0b111) as u32 * 12 + 40
     }
     pub fn enemy_kind(&self) -> EnemyKind {
-        // 50% bird / 50% cactus
-        if ((self.0 & 0b111000) >> 3) < 4 {
-            EnemyKind::Bird
-        } else {
-            EnemyKind::Cactus
+        // 50% bird / 37.5% ground hazard / 12.5% spring, with a quarter of the ground hazards
+        // spikes
+        match (self.0 & 0b111000) >> 3 {
+            0..=3 => EnemyKind::Bird,
+            4..=6 if self.enemy_arg_2bit() == 2 => EnemyKind::Spike,
+            4..=6 => EnemyKind::Cactus(self.cactus_size()),
+            _ => EnemyKind::Spring,
         }
     }
     pub fn enemy_arg_2bit(&self) -> u8 {
         (self.0 & 0b11000000) >> 6
     }
+    pub fn bird_band(&self) -> BirdBand {
+        // 25% ground / 25% mid / 50% high
+        match self.enemy_arg_2bit() {
+            0 => BirdBand::Ground,
+            1 => BirdBand::Mid,
+            _ => BirdBand::High,
+        }
+    }
+    pub fn bird_y(&self) -> i32 {
+        self.bird_band().y()
+    }
+    pub fn cactus_size(&self) -> CactusSize {
+        // 1 in 3 small / normal / tall, the arg that's left over makes a spike instead
+        match self.enemy_arg_2bit() {
+            0 => CactusSize::Small,
+            1 | 2 => CactusSize::Normal,
+            _ => CactusSize::Tall,
+        }
+    }
+    pub fn is_fast(&self) -> bool {
+        // 1 in 4 birds, flying faster than the ground scrolls
+        ((self.0 & 0b111000) >> 3) == 3
+    }
 }
 
 pub enum TextAlign {
@@ -251,408 +1044,4793 @@ pub enum TextAlign {
     Right,
 }
 
-pub fn draw_score_digits(
-    score: u32,
+const GLYPH_WIDTH: i32 = 7;
+const SEPARATOR_WIDTH: i32 = 4;
+// Advances for the glyphs in font.aseprite narrower than `GLYPH_WIDTH`, keeping a one pixel
+// gap after each. Everything else, digits included, gets `GLYPH_WIDTH`, and `None` makes all
+// text fixed width again.
+const GLYPH_ADVANCES: Option<&[(char, i32)]> = Some(&[
+    ('I', 6),
+    ('T', 6),
+    ('L', 6),
+    ('J', 6),
+    (',', 4),
+    (':', 4),
+    ('!', 4),
+    ('\'', 4),
+    (' ', 5),
+]);
+
+fn glyph_advance(char: char) -> i32 {
+    GLYPH_ADVANCES
+        .and_then(|advances| advances.iter().find(|(key, _)| *key == char))
+        .map_or(GLYPH_WIDTH, |&(_, advance)| advance)
+}
+
+// How far the digit `digit_pos` places from the right of a `digits` long number sits from its
+// left edge, after the digits and thousands separators before it
+fn digit_x(digit_pos: u32, digits: u32, separators: bool) -> i32 {
+    let separators_before = if separators {
+        (digits - 1) / 3 - digit_pos / 3
+    } else {
+        0
+    };
+    GLYPH_WIDTH * (digits - 1 - digit_pos) as i32 + SEPARATOR_WIDTH * separators_before as i32
+}
+
+pub fn draw_number(
+    value: u32,
+    min_digits: u32,
+    separators: bool,
     position: Vector2D<i32>,
     oam_frame: &mut OamIterator,
     sprite_cache: &SpriteCache,
     align: TextAlign,
 ) -> Option<()> {
-    for digit_pos in 0..6i32 {
-        let digit = (score / (10_u32.pow(digit_pos as u32))) % 10;
-        let sprite = sprite_cache.numbers.get(digit as usize).unwrap();
-        let number_relative_position: i32 = match align {
-            TextAlign::Left => 7 * (5 - digit_pos),
-            TextAlign::Center => 7 * (2 - digit_pos),
-            TextAlign::Right => 7 * (-1 - digit_pos),
-        };
-        let number_position: Vector2D<i32> =
-            (position.x + number_relative_position, position.y).into();
+    let digits = min_digits.max(value.checked_ilog10().unwrap_or(0) + 1);
+    // Thousands separators take part in the layout width so alignment stays correct
+    let width = digit_x(0, digits, separators) + GLYPH_WIDTH;
+    let left = match align {
+        TextAlign::Left => position.x,
+        TextAlign::Center => position.x - width / 2,
+        TextAlign::Right => position.x - width,
+    };
 
+    for digit_pos in (0..digits).rev() {
+        // Places beyond what a `u32` reaches can only be padding zeros
+        let digit = 10_u32
+            .checked_pow(digit_pos)
+            .map_or(0, |place| (value / place) % 10);
+        let x = left + digit_x(digit_pos, digits, separators);
+        let sprite = sprite_cache.numbers.get(digit as usize).unwrap();
         let mut object = ObjectUnmanaged::new(sprite.clone());
-        object.show().set_position(number_position);
+        object.show().set_position((x, position.y).into());
         oam_frame.next()?.set(&object);
+
+        if separators && digit_pos > 0 && digit_pos % 3 == 0 {
+            if let Some(sprite) = sprite_cache.char_sprite(',') {
+                let mut object = ObjectUnmanaged::new(sprite.clone());
+                object
+                    .show()
+                    .set_position((x + GLYPH_WIDTH, position.y).into());
+                oam_frame.next()?.set(&object);
+            }
+        }
     }
     Some(())
 }
+
+pub fn draw_score_digits(
+    score: u32,
+    separators: bool,
+    position: Vector2D<i32>,
+    oam_frame: &mut OamIterator,
+    sprite_cache: &SpriteCache,
+    align: TextAlign,
+) -> Option<()> {
+    draw_number(
+        score,
+        6,
+        separators,
+        position,
+        oam_frame,
+        sprite_cache,
+        align,
+    )
+}
+
+// A distance with its unit, e.g. "123M"
+pub fn draw_distance(
+    meters: u32,
+    position: Vector2D<i32>,
+    oam_frame: &mut OamIterator,
+    sprite_cache: &SpriteCache,
+    align: TextAlign,
+) -> Option<()> {
+    let digits = meters.checked_ilog10().unwrap_or(0) as i32 + 1;
+    let width = GLYPH_WIDTH * (digits + 1);
+    let left = match align {
+        TextAlign::Left => position.x,
+        TextAlign::Center => position.x - width / 2,
+        TextAlign::Right => position.x - width,
+    };
+
+    draw_number(
+        meters,
+        1,
+        false,
+        (left, position.y).into(),
+        oam_frame,
+        sprite_cache,
+        TextAlign::Left,
+    )?;
+    let sprite = sprite_cache.char_map.get(&'M').unwrap();
+    let mut object = ObjectUnmanaged::new(sprite.clone());
+    object
+        .show()
+        .set_position((left + GLYPH_WIDTH * digits, position.y).into());
+    oam_frame.next()?.set(&object);
+    Some(())
+}
+
+// Draws `mm:ss`, clamped to 99:59
+pub fn draw_time(
+    total_seconds: u32,
+    position: Vector2D<i32>,
+    oam_frame: &mut OamIterator,
+    sprite_cache: &SpriteCache,
+    align: TextAlign,
+) -> Option<()> {
+    let (minutes, seconds) = if total_seconds < 100 * 60 {
+        (total_seconds / 60, total_seconds % 60)
+    } else {
+        (99, 59)
+    };
+    let width = GLYPH_WIDTH * 4 + SEPARATOR_WIDTH;
+    let left = match align {
+        TextAlign::Left => position.x,
+        TextAlign::Center => position.x - width / 2,
+        TextAlign::Right => position.x - width,
+    };
+
+    draw_number(
+        minutes,
+        2,
+        false,
+        (left, position.y).into(),
+        oam_frame,
+        sprite_cache,
+        TextAlign::Left,
+    )?;
+    let sprite = sprite_cache.char_map.get(&':').unwrap();
+    let mut object = ObjectUnmanaged::new(sprite.clone());
+    object
+        .show()
+        .set_position((left + GLYPH_WIDTH * 2, position.y).into());
+    oam_frame.next()?.set(&object);
+    draw_number(
+        seconds,
+        2,
+        false,
+        (left + GLYPH_WIDTH * 2 + SEPARATOR_WIDTH, position.y).into(),
+        oam_frame,
+        sprite_cache,
+        TextAlign::Left,
+    )
+}
+const LINE_HEIGHT: i32 = 12;
+
+// Lines after a '\n' go `LINE_HEIGHT` further down, each aligned on its own. Lines entirely
+// off the top or bottom of the screen are skipped, as their y would wrap around.
 pub fn draw_str(
-    str: &'static str,
+    str: &str,
     position: Vector2D<i32>,
     oam_frame: &mut OamIterator,
     sprite_cache: &SpriteCache,
     align: TextAlign,
+) -> Option<()> {
+    for (index, line) in str.split('\n').enumerate() {
+        let y = position.y + index as i32 * LINE_HEIGHT;
+        if (-8..160).contains(&y) {
+            draw_line(
+                line,
+                (position.x, y).into(),
+                |_| 0,
+                oam_frame,
+                sprite_cache,
+                &align,
+            )?;
+        }
+    }
+    Some(())
+}
+
+// `y_offset` moves each glyph up or down by its index in the line
+fn draw_line(
+    str: &str,
+    position: Vector2D<i32>,
+    y_offset: impl Fn(usize) -> i32,
+    oam_frame: &mut OamIterator,
+    sprite_cache: &SpriteCache,
+    align: &TextAlign,
 ) -> Option<()> {
     let uppercase = str.to_uppercase();
-    let str_len = str.len();
+    let width: i32 = uppercase.chars().map(glyph_advance).sum();
+    let mut x = match align {
+        TextAlign::Left => position.x,
+        TextAlign::Center => position.x - width / 2,
+        TextAlign::Right => position.x - width,
+    };
     for (idx, char) in uppercase.chars().enumerate() {
+        let advance = glyph_advance(char);
         if char.is_whitespace() {
+            x += advance;
             continue;
         }
 
         let sprite = sprite_cache
-            .char_map
-            .get(&char)
+            .char_sprite(char)
             .unwrap_or(sprite_cache.char_map.get(&'?').unwrap());
 
         let mut object = ObjectUnmanaged::new(sprite.clone());
-        let char_relative_position: i32 = match align {
-            TextAlign::Left => 7 * idx as i32,
-            TextAlign::Center => 7 * (idx as i32 - str_len as i32 / 2),
-            TextAlign::Right => 7 * (idx as i32 - str_len as i32),
-        };
-
         object
             .show()
-            .set_position((position.x + char_relative_position, position.y).into());
+            .set_position((x, position.y + y_offset(idx)).into());
         oam_frame.next()?.set(&object);
+        x += advance;
     }
 
     Some(())
 }
 
-fn play_sound(mixer: &mut Mixer, kind: SoundEffectKind) {
-    let mut sound = get_sound(kind);
-    sound.volume(num!(0.5));
-    mixer.play_sound(sound);
-}
+const DROP_HEIGHT_PX: i32 = 72;
+const DROP_FRAMES: i32 = 12;
+const DROP_STAGGER_FRAMES: i32 = 3;
+const DROP_BOUNCE_FRAMES: i32 = 8;
+const DROP_BOUNCE_PX: i32 = 4;
 
-pub struct Game {
-    mgba: Option<Mgba>,
-    settings: Settings,
-    state: GameState,
+// Drops the letters of a single line in from above one after another, each with a little
+// bounce on landing, and holds them still once the last one has settled
+pub fn draw_str_dropping(
+    str: &str,
+    position: Vector2D<i32>,
+    frames: u32,
+    oam_frame: &mut OamIterator,
+    sprite_cache: &SpriteCache,
+    align: TextAlign,
+) -> Option<()> {
+    let y_offset = |idx: usize| {
+        let t = frames as i32 - idx as i32 * DROP_STAGGER_FRAMES;
+        if t < DROP_FRAMES {
+            // Falling faster and faster, from out of sight above the screen
+            let t = t.max(0);
+            -DROP_HEIGHT_PX * (DROP_FRAMES * DROP_FRAMES - t * t) / (DROP_FRAMES * DROP_FRAMES)
+        } else if t < DROP_FRAMES + DROP_BOUNCE_FRAMES {
+            let half = DROP_BOUNCE_FRAMES / 2;
+            let k = t - DROP_FRAMES - half;
+            -DROP_BOUNCE_PX * (half * half - k * k) / (half * half)
+        } else {
+            0
+        }
+    };
+    draw_line(str, position, y_offset, oam_frame, sprite_cache, &align)
+}
+// Draws `str` for the first `on_frames` of every `period_frames` (blink duty cycle)
+#[allow(clippy::too_many_arguments)]
+pub fn draw_str_blinking(
+    str: &str,
+    position: Vector2D<i32>,
+    period_frames: u32,
+    on_frames: u32,
     frame_count: u32,
-    speed_level: u16,
-    background_position: Vector2D<Number>,
-    scroll_velocity: Number,
-    gravity_px_per_square_frame: Number,
-    input: ButtonController,
-    player: Player,
-    enemies: VecDeque<Enemy>,
-    frames_current_level: u32,
-    frames_since_last_spawn: u32,
-    spawn_queue: VecDeque<SpawnInfo>,
+    oam_frame: &mut OamIterator,
+    sprite_cache: &SpriteCache,
+    align: TextAlign,
+) -> Option<()> {
+    if frame_count % period_frames.max(1) >= on_frames {
+        return Some(());
+    }
+    draw_str(str, position, oam_frame, sprite_cache, align)
 }
 
-fn frame_ranger(count: u32, start: u32, end: u32, delay: u32) -> usize {
-    (((count / delay) % (end + 1 - start)) + start) as usize
+const SOUND_VOLUME: Num<i16, 8> = Num::from_raw(1 << 7);
+// The whoosh's volume in 256ths: a whisper at the first level, up to a bit over half the sound
+// effects' at full speed, eased towards by `WHOOSH_FADE_STEP` a frame
+const WHOOSH_BASE_VOLUME: i16 = 3;
+const WHOOSH_VOLUME_PER_LEVEL: i16 = 6;
+const WHOOSH_MAX_VOLUME: i16 = 72;
+const WHOOSH_FADE_STEP: i16 = 2;
+
+fn play_sound(mixer: &mut Mixer, kind: SoundEffectKind) -> Option<ChannelId> {
+    let mut sound = get_sound(kind);
+    sound.volume(SOUND_VOLUME);
+    mixer.play_sound(sound)
 }
 
-impl Game {
-    pub fn from_settings(settings: Settings) -> Self {
-        let player = Player {
-            position: (16, DINO_GROUNDED_Y as i32).into(),
-            vertical_speed: Number::new(0),
-            is_jumping: false,
-        };
-        let gravity_px_per_square_frame: Number = Number::new(2 * settings.jump_height_px as i32)
-            / Number::new(settings.jump_duration_frames.pow(2) as i32);
+// What the player asked for on a frame, independent of which buttons mean what
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameInput {
+    pub jump: bool,
+    pub pause: bool,
+    pub shop: bool,
+    // Held rather than just pressed, see `QUIT_HOLD_FRAMES`
+    pub quit: bool,
+    // Held too, see `Settings::restart_hold_frames`
+    pub restart: bool,
+    // Held, and ignored in the air
+    pub duck: bool,
+    // Either jump button held, letting go on the way up cuts the jump short
+    pub jump_held: bool,
+}
 
+impl FrameInput {
+    pub fn from_buttons(input: &ButtonController) -> Self {
         Self {
-            mgba: Mgba::new(),
-            frame_count: 0,
-            frames_current_level: 0,
-            frames_since_last_spawn: 0,
+            // Either button, and both at once is still the one jump
+            jump: input.is_just_pressed(Button::A) || input.is_just_pressed(Button::UP),
+            pause: input.is_just_pressed(Button::START),
+            shop: input.is_just_pressed(Button::SELECT),
+            quit: input.is_pressed(Button::START),
+            restart: input.is_pressed(Button::A),
+            duck: input.is_pressed(Button::DOWN) || input.is_pressed(Button::B),
+            jump_held: input.is_pressed(Button::A) || input.is_pressed(Button::UP),
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            jump: self.jump || other.jump,
+            pause: self.pause || other.pause,
+            shop: self.shop || other.shop,
+            quit: self.quit || other.quit,
+            restart: self.restart || other.restart,
+            duck: self.duck || other.duck,
+            jump_held: self.jump_held || other.jump_held,
+        }
+    }
+}
+
+// Things that happen during a step which the outside world may want to react to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameEvent {
+    Jump,
+    LevelUp,
+    CoinCollected,
+    SpringLaunch,
+    LifeLost,
+    ExtraLife,
+    Over,
+    AchievementUnlocked(Achievement),
+    // Shortly after game over, when the run beat the stored hi score
+    NewHiScore,
+    // A bird or cactus went off the left edge without hitting the dino
+    EnemyDodged { kind: EnemyKind },
+    // An enemy was cleared by a hair, once for each one
+    NearMiss,
+}
+
+pub trait EventSink {
+    fn push(&mut self, event: GameEvent);
+}
+
+impl GameEvent {
+    fn sound(self) -> Option<SoundEffectKind> {
+        Some(match self {
+            GameEvent::Jump => SoundEffectKind::Jump,
+            GameEvent::LevelUp | GameEvent::AchievementUnlocked(_) => SoundEffectKind::Up,
+            GameEvent::CoinCollected => SoundEffectKind::Coin,
+            GameEvent::SpringLaunch => SoundEffectKind::Spring,
+            GameEvent::LifeLost => SoundEffectKind::Knockback,
+            GameEvent::Over => SoundEffectKind::Over,
+            GameEvent::NewHiScore => SoundEffectKind::Fanfare,
+            GameEvent::ExtraLife => SoundEffectKind::ExtraLife,
+            GameEvent::NearMiss => SoundEffectKind::NearMiss,
+            GameEvent::EnemyDodged { .. } => return None,
+        })
+    }
+}
+
+impl EventSink for Mixer<'_> {
+    fn push(&mut self, event: GameEvent) {
+        if let Some(sound) = event.sound() {
+            play_sound(self, sound);
+        }
+    }
+}
+
+// Plays events like the bare mixer, but keeps hold of the channels so pausing can freeze them,
+// and notes how long the hits and level ups want to rumble for
+struct TrackedMixer<'a, 'm> {
+    mixer: &'a mut Mixer<'m>,
+    channels: &'a mut Vec<ChannelId>,
+    rumble_frames: u16,
+}
+
+impl EventSink for TrackedMixer<'_, '_> {
+    fn push(&mut self, event: GameEvent) {
+        if let Some(channel) = event
+            .sound()
+            .and_then(|sound| play_sound(self.mixer, sound))
+        {
+            self.channels.push(channel);
+        }
+        let rumble_frames = match event {
+            GameEvent::LifeLost | GameEvent::Over => RUMBLE_HIT_FRAMES,
+            GameEvent::LevelUp => RUMBLE_LEVEL_UP_FRAMES,
+            _ => 0,
+        };
+        self.rumble_frames = self.rumble_frames.max(rumble_frames);
+    }
+}
+
+// For headless runs that don't care about events
+impl EventSink for () {
+    fn push(&mut self, _event: GameEvent) {}
+}
+
+impl EventSink for Vec<GameEvent> {
+    fn push(&mut self, event: GameEvent) {
+        Vec::push(self, event);
+    }
+}
+
+pub struct Game {
+    mgba: Option<Mgba>,
+    rumble: Rumble,
+    settings: Settings,
+    state: GameState,
+    frames_in_state: u32,
+    frame_count: u32,
+    speed_level: u16,
+    background_position: Vector2D<Number>,
+    // How fast the ground scrolls, and how fast the enemies come at the dino. They're the same
+    // unless something holds one of them back, like a stop time power-up freezing the enemies.
+    scroll_velocity: Number,
+    enemy_velocity: Number,
+    // What's scrolled short of a whole pixel, carried over to the next step
+    scroll_remainder: Number,
+    // Gravity while the dino is still going up, and once it's coming down
+    rise_gravity_px_per_square_frame: Number,
+    fall_gravity_px_per_square_frame: Number,
+    jump_speed_px_per_frame: Number,
+    // Frames of motion each step covers, 1 at the GBA's 60fps
+    delta_frames: Number,
+    input: ButtonController,
+    pending_input: FrameInput,
+    rng: RandomNumberGenerator,
+    player: Player,
+    enemies: VecDeque<Enemy>,
+    frames_current_level: u32,
+    frames_since_last_spawn: u32,
+    // Counts up to `CACTUS_FIELD_COOLDOWN_FRAMES` before another cactus field can roll in
+    frames_since_cactus_field: u32,
+    spawn_queue: VecDeque<SpawnInfo>,
+    collectibles: Vec<Collectible>,
+    frames_since_coin_spawn: u32,
+    coins_collected: u32,
+    bonus_score: u32,
+    lives_left: u8,
+    // Score milestones already paid out as extra lives
+    last_life_milestone: u32,
+    life_pulse_frames: u32,
+    // The running dino leans forward after a level up, easing back as this counts down
+    lean_frames: u16,
+    // After a life is lost, when enemies pass through the dino
+    invulnerable_frames: u32,
+    // The start of that, when the dino is drawn tinted red
+    hit_flash_frames: u32,
+    // Counting down to the run picking up again after a pause
+    resume_countdown_frames: u32,
+    magnet_frames: u32,
+    low_gravity_frames: u32,
+    // The enemies stand still while this counts down, see `enemy_velocity`
+    freeze_frames: u32,
+    last_landed_frame: Option<u32>,
+    // Horizontal push on the dino while airborne, positive is a tailwind
+    wind: Number,
+    frames_until_gust: u32,
+    // Counts down through the warning and the gust itself, 0 while calm
+    gust_frames: u32,
+    is_headwind: bool,
+    slow_motion_frames: u32,
+    slow_motion_cooldown_frames: u32,
+    near_misses: u32,
+    // For the recap on the game over screen
+    jumps: u32,
+    enemies_dodged: u32,
+    // Multiplier for the next dodge's bonus, from 1 up to `Settings::max_combo`
+    combo: u32,
+    frames_since_dodge: u32,
+    achievements: u8,
+    toast: Option<Achievement>,
+    toast_frames: u32,
+    // Debug builds only, toggled with L + R + SELECT
+    #[cfg(debug_assertions)]
+    auto_jump: bool,
+    // Debug builds only, see `enable_stress`
+    #[cfg(debug_assertions)]
+    is_stress: bool,
+    // The last `TRACE_FRAMES` steps, printed when the dino is hit
+    #[cfg(debug_assertions)]
+    trace: VecDeque<TraceFrame>,
+    // The last `TRANSITION_LOG_LEN` state changes, oldest first
+    #[cfg(debug_assertions)]
+    transitions: VecDeque<StateTransition>,
+    particles: Vec<Particle>,
+    frames_since_dust: u32,
+    // Player whose run this is, from 0
+    turn: u8,
+    quit_hold_frames: u32,
+    // Time left to confirm a restart after the first press on game over
+    restart_confirm_frames: u32,
+    restart_hold_frames: u32,
+    // Counts down through the wipe over a restart, see `WIPE_FRAMES`
+    wipe_frames: u32,
+    is_new_hi_score: bool,
+    // Index of the next `TUTORIAL` step, and how long the last one's hint stays up
+    tutorial_step: usize,
+    tutorial_hint_frames: u32,
+    weather: Weather,
+    frames_in_weather: u32,
+    // 0 while dry up to `RAIN_LEVELS` in a full downpour
+    rain_level: u16,
+    rain_drawn_level: Option<u16>,
+    // Night blend step, freeze and rain level the background palettes were last shaded for, so
+    // they're only rebuilt when a step actually changes
+    shade_applied: Option<(u16, bool, u16)>,
+    // Channels our events are playing on, muted while paused
+    sound_channels: Vec<ChannelId>,
+    whoosh_channel: Option<ChannelId>,
+    // Raw `Num<i16, 8>` volume, i.e. in 256ths
+    whoosh_volume: i16,
+}
+
+// A game that's only ever stepped, never shown, so it leaves the rumble motor's GPIO alone
+fn headless_game(settings: Settings) -> Game {
+    Game::from_settings(Settings {
+        rumble: false,
+        ..settings
+    })
+}
+
+// Replays a run headlessly from the settings it started with (seed included) and the input of
+// every frame, and checks it ends with the claimed score. Anything else, including inputs that
+// run out before the game is over, fails.
+pub fn verify_run(settings: Settings, inputs: &[FrameInput], claimed_score: u32) -> bool {
+    let mut game = headless_game(settings);
+    let state = game.advance_frames(inputs.len() as u32, inputs.iter().copied(), &mut ());
+    state == GameState::Over(claimed_score)
+}
+
+// Replays `inputs` headlessly like `verify_run` and hands back the state changes they led to,
+// e.g. to check that a pause and resume went `Continue -> Pause -> Continue` on the frames
+// expected. Only the last `TRANSITION_LOG_LEN` are kept.
+#[cfg(debug_assertions)]
+pub fn replay_transitions(settings: Settings, inputs: &[FrameInput]) -> Vec<StateTransition> {
+    let mut game = headless_game(settings);
+    game.advance_frames(inputs.len() as u32, inputs.iter().copied(), &mut ());
+    game.transitions().copied().collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RunResult {
+    pub score: u32,
+    pub distance_m: u32,
+    pub frames: u32,
+    // False when `max_frames` ran out first
+    pub is_over: bool,
+}
+
+// Plays a whole run headlessly for balancing, jumping like the debug auto jump does, until the
+// game is over or `max_frames` steps have gone by. The seed is `settings.rng_seed`.
+pub fn simulate_run(settings: Settings, max_frames: u32) -> RunResult {
+    let mut game = headless_game(settings);
+    let mut state = game.state;
+    for _ in 0..max_frames {
+        // Always full jumps, like the debug auto jump
+        let input = FrameInput {
+            jump: game.enemy_to_jump().is_some(),
+            jump_held: true,
+            ..FrameInput::default()
+        };
+        state = game.advance_frames(1, [input], &mut ());
+        // A finished tutorial quits rather than ending in game over
+        if state != GameState::Continue {
+            break;
+        }
+    }
+    RunResult {
+        score: game.current_score(),
+        distance_m: game.distance_m(),
+        frames: game.frame_count,
+        is_over: matches!(state, GameState::Over(_)),
+    }
+}
+
+// `numerator / denominator` to the nearest 1/256th, where `Num`'s division truncates
+fn rounded_ratio(numerator: i32, denominator: i32) -> Number {
+    Number::from_raw(((numerator << 8) + denominator / 2) / denominator)
+}
+
+// Gravity and launch speed for a jump that tops out at `height` after `duration` frames. The
+// dino moves by its speed before gravity is added, so the peak is `v + (v - g) + ...` over
+// `duration` frames, i.e. `g * d * (d + 1) / 2` rather than the continuous `g * d * d / 2`.
+// A zero duration is treated as a single frame.
+fn jump_arc(height_px: u16, duration_frames: u16) -> (Number, Number) {
+    let height_px = height_px as i32;
+    let duration_frames = duration_frames.max(1) as i32;
+    let gravity = rounded_ratio(2 * height_px, duration_frames * (duration_frames + 1));
+    let speed = rounded_ratio(2 * height_px, duration_frames + 1);
+    (gravity, speed)
+}
+
+const LANDING_SQUASH_FRAMES: u16 = 6;
+const MIN_COLLISION_SIZE_PX: u16 = 2;
+const LEAN_FRAMES: u16 = 30;
+// In revolutions, about 8 degrees
+const LEAN_MAX_ANGLE: Number = Num::from_raw(6);
+// Kept small so decorative particles never starve the HUD and enemies of OAM slots
+const MAX_PARTICLES: usize = 8;
+const DUST_LIFETIME_FRAMES: u16 = 18;
+// Coins come in a single arc at a time
+const COINS_PER_ARC: usize = 5;
+// Spawns rolled from each random word, one per byte. The queue is only refilled once empty, so
+// this is also as long as it gets.
+const SPAWN_BATCH: usize = 4;
+// Now and then a cactus roll brings in a row of small cactuses instead, each the least ground
+// apart a jump needs so they're cleared in a steady rhythm. It only comes with the screen clear
+// enough to take the whole row.
+const CACTUS_FIELD_SIZE: usize = 3;
+const CACTUS_FIELD_COOLDOWN_FRAMES: u32 = 60 * 30;
+const COIN_SPACING_PX: i32 = 16;
+const COIN_BONUS_SCORE: u32 = 25;
+// Scaled by the combo for each enemy dodged. The combo goes up one with every dodge and back
+// down one for every `COMBO_DECAY_FRAMES` without one.
+const DODGE_BONUS_SCORE: u32 = 5;
+const COMBO_DECAY_FRAMES: u32 = 60 * 3;
+const MAGNET_DURATION_FRAMES: u32 = 60 * 8;
+const MAGNET_RADIUS_PX: i32 = 56;
+const MAGNET_PULL_ACCELERATION: Number = Num::from_raw(1 << 6);
+const LOW_GRAVITY_DURATION_FRAMES: u32 = 60 * 6;
+// A quarter of the pull with half the launch speed keeps the jump height but doubles the airtime
+const LOW_GRAVITY_RATIO: Number = Num::from_raw(1 << 6);
+const FREEZE_DURATION_FRAMES: u32 = 60 * 4;
+const SPARKLE_LIFETIME_FRAMES: u16 = 12;
+const CLEAR_FRAMES: u32 = 60 * 50;
+const RAIN_FRAMES: u32 = 60 * 25;
+const RAIN_LEVELS: u16 = 8;
+const RAIN_FADE_STEP_FRAMES: u32 = 20;
+const RAIN_DARKEN_PERCENT: u16 = 20;
+const DINO_X: i32 = 16;
+// How far a gust can carry the dino either way. The spawn gap makes room for the forward
+// drift, and the backward one is small enough that a jump still clears any enemy.
+const DINO_MIN_X: i32 = 4;
+const DINO_MAX_X: i32 = 40;
+const DINO_RETURN_SPEED: Number = Num::from_raw(1 << 6);
+const GUST_INTERVAL_FRAMES: u32 = 60 * 15;
+// Blowing particles show up this long before the wind picks up
+const GUST_WARNING_FRAMES: u32 = 60;
+const GUST_DURATION_FRAMES: u32 = 60 * 4;
+const GUST_RAMP_FRAMES: u32 = 60;
+const GUST_MAX_WIND: Number = Num::from_raw(1 << 7);
+const GUST_PARTICLE_LIFETIME_FRAMES: u16 = 40;
+const FAST_ENEMY_SPEED_RATIO: Number = Num::from_raw(3 << 7);
+const NEAR_MISS_MARGIN_PX: u16 = 4;
+// Logic runs every other frame during slow motion, so this is about 8 game frames
+const SLOW_MOTION_FRAMES: u32 = 16;
+// Counted in game frames from the start of a slow motion, so near misses can't chain it
+const SLOW_MOTION_COOLDOWN_FRAMES: u32 = 120;
+const QUIT_HOLD_FRAMES: u32 = 60;
+const RESTART_CONFIRM_FRAMES: u32 = 120;
+// A restart sweeps a curtain across the finished run for the first half of these, and the
+// fresh run is uncovered, still waiting, for the second
+const WIPE_FRAMES: u32 = 20;
+// Held shorter than this it's just a tap to pause, so no point showing the hint
+const QUIT_HINT_FRAMES: u32 = 12;
+const FANFARE_DELAY_FRAMES: u32 = 30;
+// The game over screen has room to spare in OAM, so confetti gets more than `MAX_PARTICLES`
+const MAX_CONFETTI: usize = 24;
+const CONFETTI_LIFETIME_FRAMES: u16 = 170;
+const MAX_LIVES: u8 = 5;
+const LIFE_PULSE_FRAMES: u32 = 48;
+const INVULNERABLE_FRAMES: u32 = 60;
+const HIT_FLASH_FRAMES: u32 = 16;
+// A hop at half the jump speed, drifting back 1.5px a frame while in the air
+const KNOCKBACK_LIFT: Number = Num::from_raw(1 << 7);
+const KNOCKBACK_SPEED: Number = Num::from_raw(3 << 7);
+const TOAST_FRAMES: u32 = 150;
+const RECAP_PAGE_FRAMES: u32 = 120;
+// "3, 2, 1" at half a second each
+const RESUME_COUNT_FRAMES: u32 = 30;
+const RESUME_COUNTDOWN_FRAMES: u32 = RESUME_COUNT_FRAMES * 3;
+// All the six digit HUD has room for
+const MAX_SCORE: u32 = 999999;
+const RUMBLE_HIT_FRAMES: u16 = 20;
+const RUMBLE_LEVEL_UP_FRAMES: u16 = 6;
+// A little over the default jump's airtime
+const MIN_SPAWN_DELAY_FRAMES: u32 = 36;
+// About the dino's height, so a meter goes by every ten frames or so at the starting pace
+const PIXELS_PER_METER: u32 = 32;
+const TUTORIAL_HINT_FRAMES: u32 = 180;
+// Upright, left, upright, right: indices into the sway tags, slow enough to read as a breeze
+const CACTUS_SWAY_SEQUENCE: [usize; 4] = [1, 0, 1, 2];
+const CACTUS_SWAY_FRAMES: u32 = 20;
+// Any quicker and the wings strobe
+const MIN_BIRD_FLAP_FRAMES: u32 = 4;
+// Enemies kept in a snapshot, as many as the game ever shows
+const SNAPSHOT_ENEMIES: usize = 4;
+// With `Settings::bird_bob`, how far birds swing above and below where they spawned, and how
+// long a full swing takes
+const BIRD_BOB_PX: i32 = 4;
+const BIRD_BOB_PERIOD_FRAMES: i32 = 90;
+// A second of play before a hit, and as many enemies as are ever on screen in the game
+#[cfg(debug_assertions)]
+const TRACE_FRAMES: usize = 60;
+#[cfg(debug_assertions)]
+const TRACE_ENEMIES: usize = 3;
+// Enough for a pause, a shop visit and a game over or two
+#[cfg(debug_assertions)]
+const TRANSITION_LOG_LEN: usize = 16;
+// Enemies kept out by a stress run. They come in from the right edge back to back, a sprite
+// width apart, so once the first has scrolled across the screen stays about as full as it gets.
+#[cfg(debug_assertions)]
+const STRESS_ENEMIES: usize = 8;
+#[cfg(debug_assertions)]
+const STRESS_ENEMY_GAP_PX: i32 = 32;
+
+struct TutorialStep {
+    distance_px: i32,
+    hint: &'static str,
+    enable_birds: bool,
+    extra_spawn_gap_px: i32,
+}
+
+// Cactuses only and well spread out, then birds, with a reminder that they can be ducked, then
+// the gaps close up. Every step sets every field, so a restart picks up from the first one
+// cleanly. Distances are written as seconds at the easy pace of roughly 3px a frame.
+const TUTORIAL: [TutorialStep; 5] = [
+    TutorialStep {
+        distance_px: 0,
+        hint: "PRESS A TO JUMP",
+        enable_birds: false,
+        extra_spawn_gap_px: 96,
+    },
+    TutorialStep {
+        distance_px: 60 * 15 * 3,
+        hint: "LOOK UP! BIRDS!",
+        enable_birds: true,
+        extra_spawn_gap_px: 96,
+    },
+    TutorialStep {
+        distance_px: 60 * 25 * 3,
+        hint: "HOLD B TO DUCK",
+        enable_birds: true,
+        extra_spawn_gap_px: 96,
+    },
+    TutorialStep {
+        distance_px: 60 * 35 * 3,
+        hint: "CLOSER TOGETHER NOW",
+        enable_birds: true,
+        extra_spawn_gap_px: 0,
+    },
+    TutorialStep {
+        distance_px: 60 * 55 * 3,
+        hint: "WELL DONE!",
+        enable_birds: true,
+        extra_spawn_gap_px: 0,
+    },
+];
+
+// Where a sprite's own collision box lands on screen with the sprite at `position`
+fn placed_rect(rect: Rect<u16>, position: Vector2D<Number>) -> Rect<u16> {
+    let mut placed = rect;
+    placed.position += (position.x.floor() as u16, position.y.floor() as u16).into();
+    placed
+}
+
+// `rect` with up to `grace_px` taken off each side, though never shrunk below
+// `MIN_COLLISION_SIZE_PX` across
+fn shrunk_rect(rect: Rect<u16>, grace_px: u16) -> Rect<u16> {
+    let shrink = |size: u16| grace_px.min(size.saturating_sub(MIN_COLLISION_SIZE_PX) / 2);
+    let (x, y) = (shrink(rect.size.x), shrink(rect.size.y));
+    let mut shrunk = rect;
+    shrunk.position += (x, y).into();
+    shrunk.size -= (x * 2, y * 2).into();
+    shrunk
+}
+
+// Screen columns a placed box covers, signed so a sprite leaving past the left edge doesn't
+// wrap around like the `u16` rects do
+fn x_span(rect: Rect<u16>, position: Vector2D<Number>) -> (i32, i32) {
+    let left = position.x.floor() + rect.position.x as i32;
+    (left, left + rect.size.x as i32)
+}
+
+// Whether the boxes overlap on x at all, whatever the sprites' widths
+fn is_in_collision_window(
+    player_rect: Rect<u16>,
+    enemy_rect: Rect<u16>,
+    player_pos: Vector2D<Number>,
+    enemy_pos: Vector2D<Number>,
+) -> bool {
+    let (player_left, player_right) = x_span(player_rect, player_pos);
+    let (enemy_left, enemy_right) = x_span(enemy_rect, enemy_pos);
+    player_left < enemy_right && enemy_left < player_right
+}
+
+pub fn check_collision(
+    player_rect: Rect<u16>,
+    enemy_rect: Rect<u16>,
+    player_pos: Vector2D<Number>,
+    enemy_pos: Vector2D<Number>,
+) -> bool {
+    is_in_collision_window(player_rect, enemy_rect, player_pos, enemy_pos)
+        && placed_rect(enemy_rect, enemy_pos).touches(placed_rect(player_rect, player_pos))
+}
+
+fn frame_ranger(count: u32, start: u32, end: u32, delay: u32) -> usize {
+    (((count / delay) % (end + 1 - start)) + start) as usize
+}
+
+impl Game {
+    pub fn from_settings(settings: Settings) -> Self {
+        let player = Player {
+            position: (DINO_X, DINO_GROUNDED_Y as i32 + settings.ground_offset()).into(),
+            vertical_speed: Number::new(0),
+            is_jumping: false,
+            landing_frames: 0,
+            knockback_speed: Number::new(0),
+            is_ducking: false,
+            can_cut_jump: false,
+        };
+        let mut game = Self {
+            mgba: Mgba::new(),
+            rumble: Rumble::new(settings.rumble),
+            frame_count: 0,
+            frames_current_level: 0,
+            frames_since_last_spawn: 0,
+            frames_since_cactus_field: 0,
             speed_level: 0,
-            background_position: (0, 0).into(),
+            // Scrolling the map up moves its ground down
+            background_position: (0, -settings.ground_offset()).into(),
             scroll_velocity: settings.init_scroll_velocity,
+            enemy_velocity: settings.init_scroll_velocity,
+            scroll_remainder: Number::new(0),
             input: agb::input::ButtonController::new(),
+            pending_input: FrameInput::default(),
+            rng: RandomNumberGenerator::new_with_seed(settings.rng_seed),
             player,
             enemies: VecDeque::with_capacity(settings.max_enemies_displayed),
-            gravity_px_per_square_frame,
+            // Filled in by `set_jump_params` below
+            rise_gravity_px_per_square_frame: Number::new(0),
+            fall_gravity_px_per_square_frame: Number::new(0),
+            jump_speed_px_per_frame: Number::new(0),
+            delta_frames: Number::new(1),
             settings,
             state: GameState::Continue,
-            spawn_queue: VecDeque::with_capacity(4),
+            frames_in_state: 0,
+            spawn_queue: VecDeque::with_capacity(SPAWN_BATCH),
+            collectibles: Vec::with_capacity(COINS_PER_ARC),
+            frames_since_coin_spawn: 0,
+            coins_collected: 0,
+            bonus_score: 0,
+            lives_left: settings.extra_lives,
+            last_life_milestone: 0,
+            life_pulse_frames: 0,
+            lean_frames: 0,
+            invulnerable_frames: 0,
+            hit_flash_frames: 0,
+            resume_countdown_frames: 0,
+            magnet_frames: 0,
+            low_gravity_frames: 0,
+            freeze_frames: 0,
+            last_landed_frame: None,
+            wind: Number::new(0),
+            frames_until_gust: GUST_INTERVAL_FRAMES,
+            gust_frames: 0,
+            is_headwind: false,
+            slow_motion_frames: 0,
+            slow_motion_cooldown_frames: 0,
+            near_misses: 0,
+            jumps: 0,
+            enemies_dodged: 0,
+            combo: 1,
+            frames_since_dodge: 0,
+            achievements: settings.achievements,
+            toast: None,
+            toast_frames: 0,
+            #[cfg(debug_assertions)]
+            auto_jump: false,
+            #[cfg(debug_assertions)]
+            is_stress: false,
+            #[cfg(debug_assertions)]
+            trace: VecDeque::with_capacity(TRACE_FRAMES),
+            #[cfg(debug_assertions)]
+            transitions: VecDeque::with_capacity(TRANSITION_LOG_LEN),
+            // Confetti is the most there will ever be
+            particles: Vec::with_capacity(MAX_CONFETTI),
+            frames_since_dust: 0,
+            turn: 0,
+            quit_hold_frames: 0,
+            restart_confirm_frames: 0,
+            restart_hold_frames: 0,
+            wipe_frames: 0,
+            is_new_hi_score: false,
+            tutorial_step: 0,
+            tutorial_hint_frames: 0,
+            weather: Weather::Clear,
+            frames_in_weather: 0,
+            rain_level: 0,
+            rain_drawn_level: None,
+            shade_applied: None,
+            sound_channels: Vec::new(),
+            whoosh_channel: None,
+            whoosh_volume: 0,
+        };
+        game.set_jump_params(
+            settings.jump_height_px,
+            settings.jump_duration_frames,
+            settings.fall_duration_frames,
+        );
+        game
+    }
+
+    // The launch speed applies from the next jump, but the gravity changes straight away, so a
+    // dino already in the air finishes its arc under the new one. The launch speed and rise
+    // gravity alone decide how high a jump goes, so the fall can't change the height.
+    pub fn set_jump_params(&mut self, height_px: u16, duration_frames: u16, fall_frames: u16) {
+        self.settings.jump_height_px = height_px;
+        self.settings.jump_duration_frames = duration_frames;
+        self.settings.fall_duration_frames = fall_frames;
+        (
+            self.rise_gravity_px_per_square_frame,
+            self.jump_speed_px_per_frame,
+        ) = jump_arc(height_px, duration_frames);
+        (self.fall_gravity_px_per_square_frame, _) = jump_arc(height_px, fall_frames);
+    }
+
+    fn dino_grounded_y(&self) -> i32 {
+        DINO_GROUNDED_Y as i32 + self.settings.ground_offset()
+    }
+
+    // Starts a new run in place, for the next player when taking turns. The hi score (from a
+    // ranked run only), coin balance and achievements carry over from the run that just ended,
+    // and the next run's seed comes from this one's rng, so it isn't a replay but can still be
+    // replayed from its settings alone.
+    pub fn reset(&mut self) {
+        let mut settings = self.settings;
+        settings.rng_seed = core::array::from_fn(|_| self.rng.gen() as u32);
+        if settings.is_ranked {
+            settings.hi_score = settings.hi_score.max(self.current_score());
+        }
+        settings.last_score = self.current_score();
+        settings.coin_balance = settings.coin_balance.saturating_add(self.coins_collected);
+        settings.achievements = self.achievements;
+
+        let previous = core::mem::replace(self, Self::from_settings(settings));
+        self.mgba = previous.mgba;
+        self.turn = (previous.turn + 1) % self.settings.players.max(1);
+        self.input = previous.input;
+        self.wipe_frames = previous.wipe_frames;
+        #[cfg(debug_assertions)]
+        {
+            self.auto_jump = previous.auto_jump;
+            self.is_stress = previous.is_stress;
+        }
+    }
+
+    // What a quit run needs to carry on later from where it was, see `from_snapshot`. Coins,
+    // particles and effects on screen are left out. The rng can't be read back, so it's
+    // reseeded from itself and the new seed is stored instead.
+    pub fn snapshot(&mut self, difficulty: Difficulty) -> GameSnapshot {
+        let seed = core::array::from_fn(|_| (self.rng.gen() as u32).max(1));
+        self.rng = RandomNumberGenerator::new_with_seed(seed);
+        GameSnapshot::new(difficulty, |fields| {
+            fields.u32(self.frame_count);
+            fields.u16(self.speed_level);
+            fields.i32(self.background_position.x.to_raw());
+            fields.i32(self.background_position.y.to_raw());
+            fields.i32(self.scroll_velocity.to_raw());
+            fields.u32(self.frames_current_level);
+            fields.u32(self.frames_since_last_spawn);
+            fields.u32(self.frames_since_cactus_field);
+            fields.u32(self.frames_since_coin_spawn);
+            fields.u32(self.coins_collected);
+            fields.u32(self.bonus_score);
+            fields.u8(self.lives_left);
+            fields.u32(self.last_life_milestone);
+            fields.u32(self.invulnerable_frames);
+            fields.u32(self.magnet_frames);
+            fields.u32(self.low_gravity_frames);
+            fields.u32(self.freeze_frames);
+            fields.u32(self.frames_until_gust);
+            fields.u32(self.gust_frames);
+            fields.bool(self.is_headwind);
+            fields.u32(self.slow_motion_cooldown_frames);
+            fields.u32(self.near_misses);
+            fields.u32(self.jumps);
+            fields.u32(self.enemies_dodged);
+            fields.u32(self.combo);
+            fields.u32(self.frames_since_dodge);
+            fields.u8(self.achievements);
+            fields.u8(self.weather as u8);
+            fields.u32(self.frames_in_weather);
+            fields.u16(self.rain_level);
+            for value in seed {
+                fields.u32(value);
+            }
+
+            fields.i32(self.player.position.x.to_raw());
+            fields.i32(self.player.position.y.to_raw());
+            fields.i32(self.player.vertical_speed.to_raw());
+            fields.bool(self.player.is_jumping);
+            fields.u16(self.player.landing_frames);
+            fields.i32(self.player.knockback_speed.to_raw());
+            fields.bool(self.player.can_cut_jump);
+
+            let enemies = self.enemies.len().min(SNAPSHOT_ENEMIES);
+            fields.u8(enemies as u8);
+            for enemy in self.enemies.iter().take(enemies) {
+                fields.u8(match enemy.kind {
+                    EnemyKind::Bird => 0,
+                    EnemyKind::Cactus(CactusSize::Small) => 1,
+                    EnemyKind::Cactus(CactusSize::Normal) => 2,
+                    EnemyKind::Cactus(CactusSize::Tall) => 3,
+                    EnemyKind::Spring => 4,
+                    EnemyKind::Spike => 5,
+                });
+                fields.i32(enemy.position.x.to_raw());
+                fields.i32(enemy.position.y.to_raw());
+                fields.bool(enemy.triggered);
+                fields.bool(enemy.is_fast);
+                fields.bool(enemy.grazed);
+                fields.u32(enemy.sway_phase);
+                fields.u32(enemy.spawn_frame);
+                fields.i32(enemy.spawn_y.to_raw());
+            }
+            fields.u8(self.spawn_queue.len() as u8);
+            for spawn_info in self.spawn_queue.iter() {
+                fields.u8(spawn_info.0);
+            }
+        })
+    }
+
+    // Carries on a run from `snapshot`, paused. The settings have to be built the way the run's
+    // were, which they will be for the same difficulty, while the seed in them is unused. None
+    // if the fields don't make sense, e.g. from a build that wrote them differently.
+    pub fn from_snapshot(settings: Settings, mut fields: SnapshotReader) -> Option<Self> {
+        let mut game = Self::from_settings(settings);
+        game.frame_count = fields.u32();
+        game.speed_level = fields.u16();
+        game.background_position = (
+            Number::from_raw(fields.i32()),
+            Number::from_raw(fields.i32()),
+        )
+            .into();
+        game.scroll_velocity = Number::from_raw(fields.i32());
+        game.enemy_velocity = game.scroll_velocity;
+        game.frames_current_level = fields.u32();
+        game.frames_since_last_spawn = fields.u32();
+        game.frames_since_cactus_field = fields.u32();
+        game.frames_since_coin_spawn = fields.u32();
+        game.coins_collected = fields.u32();
+        game.bonus_score = fields.u32();
+        game.lives_left = fields.u8();
+        game.last_life_milestone = fields.u32();
+        game.invulnerable_frames = fields.u32();
+        game.magnet_frames = fields.u32();
+        game.low_gravity_frames = fields.u32();
+        game.freeze_frames = fields.u32();
+        game.frames_until_gust = fields.u32();
+        game.gust_frames = fields.u32();
+        game.is_headwind = fields.bool();
+        game.slow_motion_cooldown_frames = fields.u32();
+        game.near_misses = fields.u32();
+        game.jumps = fields.u32();
+        game.enemies_dodged = fields.u32();
+        game.combo = fields.u32().clamp(1, settings.max_combo.max(1));
+        game.frames_since_dodge = fields.u32();
+        game.achievements |= fields.u8();
+        game.weather = match fields.u8() {
+            0 => Weather::Clear,
+            1 => Weather::RainStarting,
+            2 => Weather::Raining,
+            3 => Weather::RainEnding,
+            _ => return None,
+        };
+        game.frames_in_weather = fields.u32();
+        game.rain_level = fields.u16().min(RAIN_LEVELS);
+        let seed: [u32; 4] = core::array::from_fn(|_| fields.u32());
+        if seed.contains(&0) {
+            return None;
+        }
+        game.rng = RandomNumberGenerator::new_with_seed(seed);
+
+        game.player.position = (
+            Number::from_raw(fields.i32()),
+            Number::from_raw(fields.i32()),
+        )
+            .into();
+        game.player.vertical_speed = Number::from_raw(fields.i32());
+        game.player.is_jumping = fields.bool();
+        game.player.landing_frames = fields.u16();
+        game.player.knockback_speed = Number::from_raw(fields.i32());
+        game.player.can_cut_jump = fields.bool();
+
+        let enemies = fields.u8() as usize;
+        if enemies > SNAPSHOT_ENEMIES.min(game.enemies.capacity()) {
+            return None;
+        }
+        for _ in 0..enemies {
+            let kind = match fields.u8() {
+                0 => EnemyKind::Bird,
+                1 => EnemyKind::Cactus(CactusSize::Small),
+                2 => EnemyKind::Cactus(CactusSize::Normal),
+                3 => EnemyKind::Cactus(CactusSize::Tall),
+                4 => EnemyKind::Spring,
+                5 => EnemyKind::Spike,
+                _ => return None,
+            };
+            game.enemies.push_back(Enemy {
+                kind,
+                position: (
+                    Number::from_raw(fields.i32()),
+                    Number::from_raw(fields.i32()),
+                )
+                    .into(),
+                triggered: fields.bool(),
+                is_fast: fields.bool(),
+                grazed: fields.bool(),
+                sway_phase: fields.u32(),
+                spawn_frame: fields.u32(),
+                spawn_y: Number::from_raw(fields.i32()),
+            });
+        }
+        let spawns = fields.u8() as usize;
+        if spawns > SPAWN_BATCH {
+            return None;
+        }
+        for _ in 0..spawns {
+            game.spawn_queue.push_back(SpawnInfo(fields.u8()));
+        }
+
+        game.state = GameState::Pause;
+        Some(game)
+    }
+
+    fn gravity(&self) -> Number {
+        let gravity = if self.player.vertical_speed < Number::new(0) {
+            self.rise_gravity_px_per_square_frame
+        } else {
+            self.fall_gravity_px_per_square_frame
+        };
+        if self.low_gravity_frames > 0 {
+            gravity * LOW_GRAVITY_RATIO
+        } else {
+            gravity
+        }
+    }
+
+    fn jump_speed(&self) -> Number {
+        if self.low_gravity_frames > 0 {
+            self.jump_speed_px_per_frame / 2
+        } else {
+            self.jump_speed_px_per_frame
+        }
+    }
+
+    // Starts a gust every so often and ramps the wind up and back down over it
+    fn update_wind(&mut self) {
+        if self.gust_frames == 0 {
+            self.wind = Number::new(0);
+            self.frames_until_gust = self.frames_until_gust.saturating_sub(1);
+            if self.settings.weather && self.frames_until_gust == 0 {
+                let rnd = self.rng.gen() as u32;
+                self.frames_until_gust = GUST_INTERVAL_FRAMES + (rnd & 0xFF) * 2;
+                self.is_headwind = rnd & 0x100 != 0;
+                self.gust_frames = GUST_WARNING_FRAMES + GUST_DURATION_FRAMES;
+            }
+            return;
+        }
+
+        self.gust_frames -= 1;
+        let ramp_frames = self
+            .gust_frames
+            .min(GUST_DURATION_FRAMES - self.gust_frames.min(GUST_DURATION_FRAMES))
+            .min(GUST_RAMP_FRAMES);
+        let wind = GUST_MAX_WIND * ramp_frames as i32 / GUST_RAMP_FRAMES as i32;
+        self.wind = if self.is_headwind { -wind } else { wind };
+    }
+
+    fn update_particles(&mut self) {
+        for particle in self.particles.iter_mut() {
+            particle.position += particle.velocity * self.delta_frames;
+            particle.frames_left -= 1;
+        }
+        self.particles.retain(|particle| particle.frames_left > 0);
+    }
+
+    // Fanfare and confetti on the game over screen of a record run, with fewer and slower
+    // confetti under reduced flashing
+    fn celebrate(&mut self, sink: &mut impl EventSink) {
+        if self.frames_in_state == FANFARE_DELAY_FRAMES {
+            sink.push(GameEvent::NewHiScore);
+        }
+        self.update_particles();
+
+        let (interval_frames, max_confetti, fall_speed) = if self.settings.reduced_flashing {
+            (12, MAX_PARTICLES, num!(0.5))
+        } else {
+            (3, MAX_CONFETTI, Number::new(1))
+        };
+        if self.frames_in_state % interval_frames == 0 && self.particles.len() < max_confetti {
+            let rnd = self.rng.gen() as u32;
+            let drift = Number::from_raw((rnd >> 8 & 0xFF) as i32 - 0x80);
+            self.particles.push(Particle {
+                kind: ParticleKind::Confetti {
+                    colour: (rnd >> 16) as usize,
+                },
+                position: ((rnd & 0xFF) as i32 % 240, -8).into(),
+                velocity: (drift, fall_speed).into(),
+                frames_left: CONFETTI_LIFETIME_FRAMES,
+            });
+        }
+    }
+
+    // Back to the pause screen with whatever the options menu changed
+    pub fn resume_from_options(&mut self, rumble: bool, reduced_flashing: bool) {
+        self.settings.rumble = rumble;
+        self.settings.reduced_flashing = reduced_flashing;
+        self.rumble = Rumble::new(rumble);
+        self.set_state(GameState::Pause);
+    }
+
+    fn set_state(&mut self, state: GameState) {
+        if self.state != state {
+            self.frames_in_state = 0;
+            #[cfg(debug_assertions)]
+            {
+                if self.transitions.len() == TRANSITION_LOG_LEN {
+                    self.transitions.pop_front();
+                }
+                self.transitions.push_back(StateTransition {
+                    frame: self.frame_count,
+                    from: self.state,
+                    to: state,
+                });
+            }
+        }
+        self.state = state;
+    }
+
+    #[cfg(debug_assertions)]
+    pub fn transitions(&self) -> impl Iterator<Item = &StateTransition> {
+        self.transitions.iter()
+    }
+
+    fn frames_into_night(&self) -> Option<u32> {
+        if self.settings.night_frames == 0 {
+            return None;
+        }
+        let cycle_frames = self.settings.day_frames + self.settings.night_frames;
+        let frames_into_cycle = self.frame_count % cycle_frames;
+        frames_into_cycle.checked_sub(self.settings.day_frames)
+    }
+
+    // How far into the night palette the background is, out of `NIGHT_BLEND_STEPS`, fading in
+    // at dusk and out again before dawn
+    fn night_step(&self) -> u16 {
+        let Some(frames_into_night) = self.frames_into_night() else {
+            return 0;
+        };
+        let transition_frames = self.settings.night_transition_frames;
+        if transition_frames == 0 {
+            return NIGHT_BLEND_STEPS;
+        }
+        let frames_to_dawn = self.settings.night_frames - frames_into_night;
+        let frames = (frames_into_night + 1)
+            .min(frames_to_dawn)
+            .min(transition_frames);
+        (frames * NIGHT_BLEND_STEPS as u32 / transition_frames) as u16
+    }
+
+    // Lowering the cap below the enemies already out only holds back new ones until enough
+    // have scrolled away. Only the debug stress run changes it for now.
+    #[cfg(any(test, debug_assertions))]
+    pub fn set_max_enemies_displayed(&mut self, max_enemies: usize) {
+        self.settings.max_enemies_displayed = max_enemies;
+        // Room up front, so spawning up to a raised cap doesn't grow the deque mid-frame
+        self.enemies
+            .reserve(max_enemies.saturating_sub(self.enemies.len()));
+    }
+
+    // Enemies are capped by the setting rather than the deque's capacity, which the allocator
+    // may round up
+    fn has_room_for_enemy(&self) -> bool {
+        self.enemies.len() < self.settings.max_enemies_displayed
+    }
+
+    // Frame of a looping animation with `frames` frames, advanced every animation interval
+    fn animation_frame(&self, frames: usize) -> usize {
+        frame_ranger(
+            self.frame_count,
+            0,
+            frames as u32 - 1,
+            self.settings.animation_interval_frames as u32,
+        )
+    }
+
+    // Birds flap faster as the ground speeds up, the configured interval being for the starting
+    // pace
+    fn bird_flap_frame(&self, frames: usize) -> usize {
+        // Clamped like the dust interval, so a standstill doesn't divide by zero
+        let interval = Number::new(self.settings.animation_interval_frames as i32)
+            * self.settings.init_scroll_velocity
+            / self.scroll_velocity.max(num!(0.25));
+        frame_ranger(
+            self.frame_count,
+            0,
+            frames as u32 - 1,
+            (interval.floor() as u32).max(MIN_BIRD_FLAP_FRAMES),
+        )
+    }
+
+    // Ground to leave after `enemy` before the next one: as far as it moves during a full jump,
+    // plus an enemy's width. A tall cactus has to be jumped early and only just clears, so it
+    // gets extra room to land and jump again.
+    fn min_spawn_gap_px(&self, enemy: &Enemy) -> i32 {
+        let jump_frames = self.settings.jump_duration_frames + self.settings.fall_duration_frames;
+        let velocity = if enemy.is_fast {
+            self.scroll_velocity * FAST_ENEMY_SPEED_RATIO
+        } else {
+            self.scroll_velocity
+        };
+        let gap = (velocity * jump_frames as i32).floor() + 32;
+        let gap = gap + self.settings.extra_spawn_gap_px;
+        let gap = match enemy.kind {
+            EnemyKind::Cactus(CactusSize::Tall) => gap + 16,
+            _ => gap,
+        };
+        if self.settings.weather {
+            gap + DINO_MAX_X - DINO_X
+        } else {
+            gap
+        }
+    }
+
+    // Cactuses stand in when both kinds are turned off, so there's always something to spawn
+    fn is_enemy_enabled(&self, kind: &EnemyKind) -> bool {
+        match kind {
+            EnemyKind::Bird => self.settings.enable_birds,
+            EnemyKind::Cactus(_) | EnemyKind::Spike => {
+                self.settings.enable_cactus || !self.settings.enable_birds
+            }
+            EnemyKind::Spring => true,
+        }
+    }
+
+    // Applies each `TUTORIAL` step once the ground has scrolled far enough, and heads back to
+    // the title once the last hint has been read
+    fn update_tutorial(&mut self) {
+        self.tutorial_hint_frames = self.tutorial_hint_frames.saturating_sub(1);
+        match TUTORIAL.get(self.tutorial_step) {
+            Some(step) if self.background_position.x.floor() >= step.distance_px => {
+                self.settings.enable_birds = step.enable_birds;
+                self.settings.extra_spawn_gap_px = step.extra_spawn_gap_px;
+                self.tutorial_step += 1;
+                self.tutorial_hint_frames = TUTORIAL_HINT_FRAMES;
+            }
+            None if self.tutorial_hint_frames == 0 => self.set_state(GameState::Quit),
+            _ => {}
+        }
+    }
+
+    // The rolled delay stretched or squeezed for the difficulty, but never so short that the
+    // dino can't land between two enemies
+    fn spawn_delay(&self, spawn_info: &SpawnInfo) -> u32 {
+        let delay = Number::new(spawn_info.delay() as i32) * self.settings.spawn_delay_factor;
+        (delay.floor().max(0) as u32).max(MIN_SPAWN_DELAY_FRAMES)
+    }
+
+    // The queued spawns with how many frames from now each one is due. Only approximate, a
+    // spawn that finds the screen full waits for room and the queue refills with fresh rolls
+    // once empty
+    pub fn upcoming_spawns(&self) -> impl Iterator<Item = (SpawnInfo, u32)> + '_ {
+        let mut frames = 0;
+        self.spawn_queue
+            .iter()
+            .enumerate()
+            .map(move |(index, spawn_info)| {
+                let elapsed = if index == 0 {
+                    self.frames_since_last_spawn
+                } else {
+                    0
+                };
+                frames += (self.spawn_delay(spawn_info) + 1).saturating_sub(elapsed);
+                (*spawn_info, frames)
+            })
+    }
+
+    fn is_jump_cooling_down(&self) -> bool {
+        self.last_landed_frame
+            .is_some_and(|landed| self.frame_count - landed < self.settings.jump_cooldown_frames)
+    }
+
+    pub fn turn(&self) -> u8 {
+        self.turn
+    }
+
+    pub fn coins_collected(&self) -> u32 {
+        self.coins_collected
+    }
+
+    // Ground covered this run, whole meters only so the HUD and the saved best always agree
+    pub fn distance_m(&self) -> u32 {
+        self.background_position.x.floor().max(0) as u32 / PIXELS_PER_METER
+    }
+
+    // Earned so far including this run, as flags for the save
+    pub fn achievements(&self) -> u8 {
+        self.achievements
+    }
+
+    // Never while the run is going, whatever text is up, as loading it takes long enough to
+    // drop a frame
+    pub fn needs_overlay(&self) -> bool {
+        matches!(
+            self.state,
+            GameState::Pause | GameState::Over(_) | GameState::Shop | GameState::Options
+        )
+    }
+
+    fn current_score(&self) -> u32 {
+        let frames = if self.settings.speed_weighted_score {
+            // In raw units, since the pixels over the velocity overflows a `Number` long before
+            // a run is over
+            let scrolled_px = self.background_position.x.floor().max(0) as u32;
+            let velocity = self.settings.init_scroll_velocity.to_raw().max(1) as u32;
+            scrolled_px.saturating_mul(1 << 8) / velocity
+        } else {
+            self.frame_count
+        };
+        (frames / self.settings.frames_per_point.max(1))
+            .saturating_add(self.bonus_score)
+            .min(MAX_SCORE)
+    }
+
+    // Runs `steps` whole steps before the next render, to fast-forward or catch up on dropped
+    // frames. Presses go to the first step only and the rest see just the held buttons, so it
+    // plays exactly as that many separate frames with nothing newly pressed would. It stops
+    // early once the run is no longer going, leaving whatever happened for the caller.
+    pub fn frame(
+        &mut self,
+        vram: &mut VRamManager,
+        background: &mut InfiniteScrolledMap<'_>,
+        rain: &mut MapLoan<'_, RegularMap>,
+        mixer: &mut Mixer<'_>,
+        delta_frames: Number,
+        steps: u32,
+    ) -> GameState {
+        self.delta_frames = delta_frames;
+        self.input.update();
+        let pressed = FrameInput::from_buttons(&self.input);
+        let held = FrameInput {
+            quit: pressed.quit,
+            restart: pressed.restart,
+            duck: pressed.duck,
+            jump_held: pressed.jump_held,
+            ..FrameInput::default()
+        };
+        // A muted channel isn't mixed at all, so it keeps its place until it's turned back up
+        let was_paused = self.state == GameState::Pause;
+        let mut channels = core::mem::take(&mut self.sound_channels);
+        channels.retain(|id| mixer.channel(id).is_some());
+        let mut sink = TrackedMixer {
+            mixer,
+            channels: &mut channels,
+            rumble_frames: 0,
+        };
+        let mut state = self.state;
+        for index in 0..steps.max(1) {
+            #[allow(unused_mut)]
+            let mut input = if index == 0 { pressed } else { held };
+            #[cfg(debug_assertions)]
+            self.apply_auto_jump(&mut input);
+            #[cfg(debug_assertions)]
+            self.fill_for_stress();
+            state = self.advance_frames(1, [input], &mut sink);
+            if state != GameState::Continue {
+                break;
+            }
+        }
+        self.rumble.frame();
+        // Paused, or off to a screen that won't tick the pulse down, so the motor goes quiet
+        match state {
+            GameState::Continue | GameState::Over(_) => self.rumble.pulse(sink.rumble_frames),
+            _ => self.rumble.stop(),
+        }
+        for id in &channels {
+            let Some(channel) = mixer.channel(id) else {
+                continue;
+            };
+            match (was_paused, state) {
+                (false, GameState::Pause) => {
+                    channel.volume(0);
+                }
+                (true, GameState::Continue) => {
+                    channel.volume(SOUND_VOLUME);
+                }
+                (_, GameState::Quit) => channel.stop(),
+                _ => {}
+            }
+        }
+        self.sound_channels = channels;
+        self.update_whoosh(mixer, state);
+
+        background.set_pos(vram, self.background_position.floor());
+
+        self.draw_rain(vram, rain);
+
+        let shade = (self.night_step(), self.freeze_frames > 0, self.rain_level);
+        if self.shade_applied != Some(shade) {
+            let darken_percent = RAIN_DARKEN_PERCENT * shade.2 / RAIN_LEVELS;
+            vram.set_background_palettes(&create_shaded_palettes(shade.0, shade.1, darken_percent));
+            self.shade_applied = Some(shade);
+        }
+        state
+    }
+
+    // Fills in more of the rain layer the heavier the rain, from a fixed hash of each tile so
+    // streaks only ever get added or removed, and keeps it falling
+    fn draw_rain(&mut self, vram: &mut VRamManager, rain: &mut MapLoan<'_, RegularMap>) {
+        if self.rain_drawn_level != Some(self.rain_level) {
+            let tileset = RAIN_TILES_DATA.tiles;
+            for y in 0..32u16 {
+                for x in 0..32u16 {
+                    let hash = (x as u32 * 32 + y as u32).wrapping_mul(0x9E37_79B1) >> 24;
+                    let tile_setting = if hash % (RAIN_LEVELS as u32 * 2) < self.rain_level as u32 {
+                        RAIN_TILES_DATA.tile_settings[hash as usize % RAIN_TILE_COUNT]
+                    } else {
+                        TileSetting::BLANK
+                    };
+                    rain.set_tile(vram, (x, y).into(), &tileset, tile_setting);
+                }
+            }
+            self.rain_drawn_level = Some(self.rain_level);
+        }
+
+        // Slower with reduced flashing, for a steady drizzle rather than a flicker of streaks
+        let fall_speed = if self.settings.reduced_flashing { 2 } else { 4 };
+        // Wrapped to a multiple of the 256px map so it loops seamlessly on both axes
+        let fallen = ((self.frame_count * fall_speed) % 512) as i16;
+        rain.set_scroll_pos((fallen / 2, -fallen).into());
+        rain.commit(vram);
+    }
+
+    // Clear skies for a while, then rain that thickens, holds and thins out again
+    fn update_weather(&mut self) {
+        self.frames_in_weather += 1;
+        let fade_step = self.frames_in_weather % RAIN_FADE_STEP_FRAMES == 0;
+        let next = match self.weather {
+            Weather::Clear if self.settings.weather && self.frames_in_weather >= CLEAR_FRAMES => {
+                Some(Weather::RainStarting)
+            }
+            Weather::RainStarting if fade_step => {
+                self.rain_level += 1;
+                (self.rain_level == RAIN_LEVELS).then_some(Weather::Raining)
+            }
+            Weather::Raining if self.frames_in_weather >= RAIN_FRAMES => Some(Weather::RainEnding),
+            Weather::RainEnding if fade_step => {
+                self.rain_level -= 1;
+                (self.rain_level == 0).then_some(Weather::Clear)
+            }
+            _ => None,
+        };
+        if let Some(weather) = next {
+            self.weather = weather;
+            self.frames_in_weather = 0;
+        }
+    }
+
+    // A looping whoosh on its own channel while there's something on screen to rush past,
+    // louder the faster the run goes. Pausing, the resume countdown, game over or leaving cut
+    // it outright, so it only plays while the run moves, and it fades back in from silence
+    // afterwards.
+    fn update_whoosh(&mut self, mixer: &mut Mixer<'_>, state: GameState) {
+        let target = if state != GameState::Continue || self.resume_countdown_frames > 0 {
+            self.whoosh_volume = 0;
+            0
+        } else if self.enemies.is_empty() {
+            0
+        } else {
+            let level = self.speed_level.min(WHOOSH_MAX_VOLUME as u16) as i16;
+            (WHOOSH_BASE_VOLUME + WHOOSH_VOLUME_PER_LEVEL * level).min(WHOOSH_MAX_VOLUME)
+        };
+        self.whoosh_volume +=
+            (target - self.whoosh_volume).clamp(-WHOOSH_FADE_STEP, WHOOSH_FADE_STEP);
+
+        if self.whoosh_volume == 0 {
+            if let Some(channel) = self.whoosh_channel.take().and_then(|id| mixer.channel(&id)) {
+                channel.stop();
+            }
+            return;
+        }
+        if self
+            .whoosh_channel
+            .as_ref()
+            .and_then(|id| mixer.channel(id))
+            .is_none()
+        {
+            self.whoosh_channel = mixer.play_sound(get_whoosh_sound());
+        }
+        if let Some(channel) = self
+            .whoosh_channel
+            .as_ref()
+            .and_then(|id| mixer.channel(id))
+        {
+            channel.volume(Num::from_raw(self.whoosh_volume));
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn record_trace(&mut self, input: FrameInput) {
+        if self.trace.len() == TRACE_FRAMES {
+            self.trace.pop_front();
+        }
+        let mut enemies = self.enemies.iter();
+        self.trace.push_back(TraceFrame {
+            frame: self.frame_count,
+            input,
+            player: self.player.position,
+            enemies: core::array::from_fn(|_| {
+                enemies.next().map(|enemy| (enemy.kind, enemy.position))
+            }),
+        });
+    }
+
+    // Prints the steps leading up to a hit, oldest first, to check a collision against what
+    // was actually on screen
+    #[cfg(debug_assertions)]
+    fn dump_trace(&mut self) {
+        for frame in self.trace.iter() {
+            print_info(
+                &mut self.mgba,
+                format_args!(
+                    "[T={}] trace: {:?} dino {:?} enemies {:?}",
+                    frame.frame, frame.input, frame.player, frame.enemies
+                ),
+            );
+        }
+        for transition in self.transitions.iter() {
+            print_info(
+                &mut self.mgba,
+                format_args!(
+                    "[T={}] state: {:?} -> {:?}",
+                    transition.frame, transition.from, transition.to
+                ),
+            );
+        }
+    }
+
+    // Stress test helper which keeps the dino jumping over whatever comes close, so a run can
+    // go on unattended long enough to reach the late game
+    #[cfg(debug_assertions)]
+    fn apply_auto_jump(&mut self, input: &mut FrameInput) {
+        if self.input.is_pressed(Button::L)
+            && self.input.is_pressed(Button::R)
+            && self.input.is_just_pressed(Button::SELECT)
+        {
+            self.auto_jump = !self.auto_jump;
+            print_info(
+                &mut self.mgba,
+                format_args!("auto jump: {}", self.auto_jump),
+            );
+        }
+        if !self.auto_jump {
+            return;
+        }
+        // Never cut short, since the jump is timed for its full height
+        input.jump_held = true;
+        if let Some((kind, x)) = self
+            .enemy_to_jump()
+            .map(|enemy| (enemy.kind, enemy.position.x.floor()))
+        {
+            print_info(
+                &mut self.mgba,
+                format_args!("[T={}] auto jump: {:?} at {}", self.frame_count, kind, x),
+            );
+            input.jump = true;
+        }
+    }
+
+    // A run that never ends, kept at the worst case for profiling `frame` and `render`: every
+    // enemy slot taken, particles and power-ups maxed out and the HUD showing all it can
+    #[cfg(debug_assertions)]
+    pub fn enable_stress(&mut self) {
+        self.is_stress = true;
+        self.auto_jump = true;
+        self.set_max_enemies_displayed(STRESS_ENEMIES);
+    }
+
+    // Enemies pass through the dino so nothing ends the run, but they're still checked for
+    // collision like any other
+    #[cfg(debug_assertions)]
+    fn fill_for_stress(&mut self) {
+        if !self.is_stress || self.state != GameState::Continue {
+            return;
+        }
+        self.lives_left = MAX_LIVES;
+        // Short enough to never reach the blink
+        self.invulnerable_frames = self.invulnerable_frames.max(2);
+        self.magnet_frames = MAGNET_DURATION_FRAMES;
+        self.low_gravity_frames = LOW_GRAVITY_DURATION_FRAMES;
+        self.toast = Some(Achievement::ALL[0]);
+        self.toast_frames = TOAST_FRAMES;
+        self.frames_since_coin_spawn = self.settings.coin_spawn_interval_frames;
+
+        let mut spawn_x = self
+            .enemies
+            .iter()
+            .map(|enemy| enemy.position.x.floor() + STRESS_ENEMY_GAP_PX)
+            .fold(8 * 30, i32::max);
+        while self.has_room_for_enemy() {
+            let (kind, y) = match self.enemies.len() % 3 {
+                0 => (EnemyKind::Bird, BirdBand::Mid.y()),
+                1 => (EnemyKind::Cactus(CactusSize::Tall), CACTUS_Y as i32),
+                _ => (EnemyKind::Spike, SPIKE_Y as i32),
+            };
+            let y = y + self.settings.ground_offset();
+            self.enemies.push_back(Enemy {
+                kind,
+                position: (spawn_x, y).into(),
+                triggered: false,
+                is_fast: false,
+                grazed: false,
+                sway_phase: self.frame_count,
+                spawn_frame: self.frame_count,
+                spawn_y: y.into(),
+            });
+            spawn_x += STRESS_ENEMY_GAP_PX;
+        }
+
+        while self.particles.len() < MAX_PARTICLES {
+            let offset = self.rng.gen() as u32;
+            self.particles.push(Particle {
+                kind: ParticleKind::Sparkle,
+                position: self.player.position
+                    + ((offset & 0x1F) as i32, ((offset >> 5) & 0x1F) as i32).into(),
+                velocity: (-self.scroll_velocity, Number::new(0)).into(),
+                frames_left: SPARKLE_LIFETIME_FRAMES,
+            });
+        }
+    }
+
+    // The enemy a simple player would jump for now: the first hazard within about eight frames
+    // of the dino, while it's on the ground and the run is going. Birds flying over it count
+    // too, so it's far from perfect.
+    fn enemy_to_jump(&self) -> Option<&Enemy> {
+        if self.player.is_jumping || self.state != GameState::Continue {
+            return None;
+        }
+        let lookahead_px = (self.enemy_velocity * 8).floor() + 32;
+        let player_x = self.player.position.x.floor();
+        self.enemies.iter().find(|enemy| {
+            enemy.kind != EnemyKind::Spring
+                && (player_x..player_x + lookahead_px).contains(&enemy.position.x.floor())
+        })
+    }
+
+    // Runs the simulation `n` times without touching the hardware, taking one input per step
+    // (missing ones count as no buttons pressed). Stops early once the run is over.
+    pub fn advance_frames(
+        &mut self,
+        n: u32,
+        inputs: impl IntoIterator<Item = FrameInput>,
+        sink: &mut impl EventSink,
+    ) -> GameState {
+        let mut inputs = inputs.into_iter();
+        for _ in 0..n {
+            let input = inputs.next().unwrap_or_default();
+            if let GameState::Over(_) = self.step(input, sink) {
+                break;
+            }
+        }
+        self.state
+    }
+
+    fn step(&mut self, input: FrameInput, sink: &mut impl EventSink) -> GameState {
+        // Presses made during a skipped slow motion frame are applied on the next step
+        let input = input.merge(self.pending_input);
+        self.pending_input = FrameInput::default();
+
+        // Slow motion skips every other frame entirely, so the score, physics and input all
+        // advance exactly as they would at full speed, just half as often
+        if self.slow_motion_frames > 0 && self.state == GameState::Continue {
+            self.slow_motion_frames -= 1;
+            if self.slow_motion_frames % 2 == 1 {
+                self.pending_input = input;
+                return self.state;
+            }
+        }
+
+        self.frames_in_state = self.frames_in_state.saturating_add(1);
+
+        // Nothing else happens under the wipe, and a press skips whatever is left of it
+        if self.wipe_frames > 0 {
+            if input.jump || input.pause {
+                self.wipe_frames = 0;
+            } else {
+                self.wipe_frames -= 1;
+            }
+            if matches!(self.state, GameState::Over(_)) && self.wipe_frames <= WIPE_FRAMES / 2 {
+                self.set_state(GameState::Restart);
+            }
+            return self.state;
+        }
+
+        // START pauses as soon as it's pressed, and keeping it held from there quits. Only a
+        // hold that began in the run counts, not one carried over from the title.
+        let can_quit = matches!(self.state, GameState::Continue | GameState::Pause);
+        if input.quit && can_quit && (input.pause || self.quit_hold_frames > 0) {
+            self.quit_hold_frames += 1;
+            if self.quit_hold_frames >= QUIT_HOLD_FRAMES {
+                self.set_state(GameState::Quit);
+                return self.state;
+            }
+        } else {
+            self.quit_hold_frames = 0;
+        }
+
+        if input.pause {
+            match self.state {
+                GameState::Continue => {
+                    // Pausing mid-count starts it over on the next resume
+                    self.resume_countdown_frames = 0;
+                    self.set_state(GameState::Pause);
+                    return self.state;
+                }
+                GameState::Pause => {
+                    self.set_state(GameState::Continue);
+                    if self.settings.resume_countdown {
+                        self.resume_countdown_frames = RESUME_COUNTDOWN_FRAMES;
+                    }
+                    return self.state;
+                }
+                _ => {}
+            };
+        }
+
+        match self.state {
+            GameState::Over(_) => {
+                if self.is_new_hi_score {
+                    self.celebrate(sink);
+                }
+                self.restart_confirm_frames = self.restart_confirm_frames.saturating_sub(1);
+                // Like quitting, only a hold that began on this screen counts, not the jump
+                // that was held into the hit
+                if input.restart && (input.jump || self.restart_hold_frames > 0) {
+                    self.restart_hold_frames += 1;
+                } else {
+                    self.restart_hold_frames = 0;
+                }
+                let is_held = self.settings.restart_hold_frames > 0
+                    && self.restart_hold_frames >= self.settings.restart_hold_frames;
+                if is_held {
+                    self.restart();
+                } else if input.jump || input.pause {
+                    if !self.settings.confirm_restart || self.restart_confirm_frames > 0 {
+                        self.restart();
+                    } else {
+                        self.restart_confirm_frames = RESTART_CONFIRM_FRAMES;
+                    }
+                } else if input.shop && self.settings.players == 1 {
+                    // The shop starts over from a fresh game, which would lose whose turn it is
+                    self.set_state(GameState::Shop);
+                }
+                return self.state;
+            }
+            GameState::Pause if input.shop => {
+                self.set_state(GameState::Options);
+                return self.state;
+            }
+            GameState::Pause | GameState::Quit | GameState::Options => {
+                return self.state;
+            }
+            _ => {}
+        }
+
+        // Frozen until the count runs out, ignoring everything but another pause
+        if self.resume_countdown_frames > 0 {
+            self.resume_countdown_frames -= 1;
+            return self.state;
+        }
+
+        self.frame_count += 1;
+        self.frames_current_level += 1;
+        #[cfg(debug_assertions)]
+        self.record_trace(input);
+        if self.settings.tutorial {
+            self.update_tutorial();
+        }
+        self.frames_since_last_spawn += 1;
+        self.frames_since_cactus_field += 1;
+
+        // Update random spawn info, rolling again for any kind that's turned off
+        while self.spawn_queue.is_empty() {
+            let rnd = self.rng.gen() as u32;
+            for i in 0..SPAWN_BATCH {
+                let spawn_info = SpawnInfo::from(((rnd >> (i * 8)) & 0xFF) as u8);
+                if self.is_enemy_enabled(&spawn_info.enemy_kind()) {
+                    debug_assert!(self.spawn_queue.len() < self.spawn_queue.capacity());
+                    self.spawn_queue.push_back(spawn_info);
+                }
+            }
+        }
+
+        // Process level up
+        if self.frames_current_level >= self.settings.frames_to_level_up
+            && self.speed_level < self.settings.max_speed_level
+        {
+            print_info(
+                &mut self.mgba,
+                format_args!("level up: {}", self.speed_level + 1),
+            );
+            sink.push(GameEvent::LevelUp);
+
+            self.scroll_velocity += self.settings.scroll_velocity_increase_per_level;
+            self.speed_level += 1;
+            self.frames_current_level = 0;
+            self.lean_frames = LEAN_FRAMES;
+        }
+        self.lean_frames = self.lean_frames.saturating_sub(1);
+
+        // Frozen enemies pick the pace straight back up once the freeze runs out, and can still
+        // be run into in the meantime
+        self.freeze_frames = self.freeze_frames.saturating_sub(1);
+        self.enemy_velocity = if self.freeze_frames > 0 {
+            Number::new(0)
+        } else {
+            self.scroll_velocity
+        };
+
+        // The ground only ever moves by whole pixels, and the coins and enemies keeping pace
+        // with it move by the very same step. Each rounding its own position on its own would
+        // have them take their extra pixel on different frames and judder against each other.
+        self.scroll_remainder += self.scroll_velocity * self.delta_frames;
+        let scroll_step = Number::new(self.scroll_remainder.floor());
+        self.scroll_remainder -= scroll_step;
+        let enemy_step = if self.enemy_velocity == self.scroll_velocity {
+            scroll_step
+        } else {
+            self.enemy_velocity * self.delta_frames
+        };
+
+        // Calc player position. Low gravity running out mid-jump just brings the dino down
+        // faster from wherever it is.
+        self.low_gravity_frames = self.low_gravity_frames.saturating_sub(1);
+        self.update_wind();
+        self.update_weather();
+        self.player.landing_frames = self.player.landing_frames.saturating_sub(1);
+        if self.player.is_jumping {
+            // Once only, and only on the way up, so the fall keeps the usual gravity
+            if self.player.can_cut_jump
+                && !input.jump_held
+                && self.player.vertical_speed < Number::new(0)
+            {
+                self.player.vertical_speed *= self.settings.min_jump_cut_multiplier;
+                self.player.can_cut_jump = false;
+            }
+            self.player.position.y += self.player.vertical_speed * self.delta_frames;
+            let player_y_px = self.player.position.y.floor();
+            if player_y_px >= self.dino_grounded_y() {
+                self.player.position.y = Num::new(self.dino_grounded_y());
+                self.player.is_jumping = false;
+                self.player.can_cut_jump = false;
+                self.player.landing_frames = LANDING_SQUASH_FRAMES;
+                self.player.knockback_speed = Number::new(0);
+                self.last_landed_frame = Some(self.frame_count);
+            }
+            self.player.vertical_speed += self.gravity() * self.delta_frames;
+            self.player.position.x += (self.wind + self.player.knockback_speed) * self.delta_frames;
+        } else if input.jump && !self.is_jump_cooling_down() {
+            sink.push(GameEvent::Jump);
+            self.jumps += 1;
+
+            self.player.vertical_speed = -self.jump_speed();
+            self.player.is_jumping = true;
+            self.player.landing_frames = 0;
+            self.player.can_cut_jump = true;
+        }
+        // A jump wins over a duck, and letting go stands the dino straight back up
+        self.player.is_ducking = input.duck && !self.player.is_jumping;
+
+        // Walk back to the usual spot once a gust has carried the dino off it
+        if !self.player.is_jumping {
+            let offset = Number::new(DINO_X) - self.player.position.x;
+            let max_step = DINO_RETURN_SPEED * self.delta_frames;
+            self.player.position.x += offset.clamp(-max_step, max_step);
+        }
+        self.player.position.x = self
+            .player
+            .position
+            .x
+            .clamp(Number::new(DINO_MIN_X), Number::new(DINO_MAX_X));
+
+        // Update particles and emit running dust, more often the faster the ground scrolls
+        self.update_particles();
+        self.frames_since_dust += 1;
+        // Clamped so settings that start the ground at a standstill don't divide by zero
+        let dust_interval_frames = (num!(24.0) / self.scroll_velocity.max(num!(0.25)))
+            .floor()
+            .max(2) as u32;
+        if !self.player.is_jumping
+            && self.frames_since_dust >= dust_interval_frames
+            && self.particles.len() < MAX_PARTICLES
+        {
+            self.frames_since_dust = 0;
+            self.particles.push(Particle {
+                kind: ParticleKind::Dust,
+                position: self.player.position + (6, 24).into(),
+                velocity: (-self.scroll_velocity, num!(-0.25)).into(),
+                frames_left: DUST_LIFETIME_FRAMES,
+            });
+        }
+        if self.low_gravity_frames > 0
+            && self.player.is_jumping
+            && self.frame_count % 4 == 0
+            && self.particles.len() < MAX_PARTICLES
+        {
+            let offset = self.rng.gen() as u32;
+            self.particles.push(Particle {
+                kind: ParticleKind::Sparkle,
+                position: self.player.position
+                    + ((offset & 0xF) as i32 + 4, ((offset >> 4) & 0xF) as i32 + 8).into(),
+                velocity: (-self.scroll_velocity, Number::new(0)).into(),
+                frames_left: SPARKLE_LIFETIME_FRAMES,
+            });
+        }
+        if self.gust_frames > 0 && self.frame_count % 6 == 0 && self.particles.len() < MAX_PARTICLES
+        {
+            let y = (self.rng.gen() as u32 & 0x3F) as i32 + 24;
+            let (x, speed) = if self.is_headwind { (240, -4) } else { (-8, 4) };
+            self.particles.push(Particle {
+                kind: ParticleKind::Gust,
+                position: (x, y).into(),
+                velocity: (Number::new(speed), Number::new(0)).into(),
+                frames_left: GUST_PARTICLE_LIFETIME_FRAMES,
+            });
+        }
+
+        // Spawn a coin arc following the jump curve, so a jump timed at the first coin
+        // picks up the whole arc
+        self.frames_since_coin_spawn += 1;
+        if self.collectibles.is_empty()
+            && self.frames_since_coin_spawn >= self.settings.coin_spawn_interval_frames
+        {
+            self.frames_since_coin_spawn = 0;
+            // One arc in four carries a magnet at its top, one in eight a feather and one in
+            // eight a snowflake, unless that power-up is already running
+            let apex_kind = match self.rng.gen() & 0b111 {
+                0 | 1 if self.magnet_frames == 0 => CollectibleKind::Magnet,
+                2 if self.low_gravity_frames == 0 => CollectibleKind::Feather,
+                3 if self.freeze_frames == 0 => CollectibleKind::Snowflake,
+                _ => CollectibleKind::Coin,
+            };
+            let half = (COINS_PER_ARC / 2) as i32;
+            for i in 0..COINS_PER_ARC as i32 {
+                let k = i - half;
+                let height =
+                    self.settings.jump_height_px as i32 * (half * half - k * k) / (half * half);
+                self.collectibles.push(Collectible {
+                    kind: if k == 0 {
+                        apex_kind
+                    } else {
+                        CollectibleKind::Coin
+                    },
+                    position: (
+                        8 * 30 + i * COIN_SPACING_PX,
+                        self.dino_grounded_y() + 12 - height,
+                    )
+                        .into(),
+                    pull_speed: Number::new(0),
+                });
+            }
+        }
+
+        // Spawn enemy. With the screen full the next one waits at the front of the queue and
+        // comes in as soon as there's room, the cap being free to change between frames.
+        if self.frames_since_last_spawn > self.spawn_delay(self.spawn_queue.front().unwrap())
+            && self.has_room_for_enemy()
+        {
+            let spawn_info = self.spawn_queue.pop_front().unwrap();
+            let delay = self.spawn_delay(&spawn_info);
+            print_info(
+                &mut self.mgba,
+                format_args!(
+                    "[T={}, dt={}] spawn: {} {:?} {}",
+                    self.frame_count,
+                    self.frames_since_last_spawn,
+                    delay,
+                    spawn_info.enemy_kind(),
+                    spawn_info.enemy_arg_2bit()
+                ),
+            );
+            self.frames_since_last_spawn = 0;
+
+            let is_cactus_field = matches!(spawn_info.enemy_kind(), EnemyKind::Cactus(_))
+                && self.frames_since_cactus_field >= CACTUS_FIELD_COOLDOWN_FRAMES
+                && self.enemies.len() + CACTUS_FIELD_SIZE <= self.settings.max_enemies_displayed;
+            let (kind, count) = if is_cactus_field {
+                print_info(&mut self.mgba, format_args!("cactus field"));
+                self.frames_since_cactus_field = 0;
+                (EnemyKind::Cactus(CactusSize::Small), CACTUS_FIELD_SIZE)
+            } else {
+                (spawn_info.enemy_kind(), 1)
+            };
+
+            // Keep clear of the rightmost enemy by enough ground to land and jump again
+            let mut spawn_x = self
+                .enemies
+                .iter()
+                .map(|enemy| enemy.position.x.floor() + self.min_spawn_gap_px(enemy))
+                .fold(8 * 30, i32::max);
+            for _ in 0..count {
+                let enemy = match kind {
+                    EnemyKind::Bird => Enemy {
+                        kind: EnemyKind::Bird,
+                        position: (spawn_x, spawn_info.bird_y() + self.settings.ground_offset())
+                            .into(),
+                        triggered: false,
+                        is_fast: spawn_info.is_fast(),
+                        grazed: false,
+                        sway_phase: 0,
+                        spawn_frame: self.frame_count,
+                        spawn_y: (spawn_info.bird_y() + self.settings.ground_offset()).into(),
+                    },
+                    EnemyKind::Cactus(size) => Enemy {
+                        kind: EnemyKind::Cactus(size),
+                        position: (spawn_x, CACTUS_Y as i32 + self.settings.ground_offset()).into(),
+                        triggered: false,
+                        is_fast: false,
+                        grazed: false,
+                        sway_phase: self.frame_count,
+                        spawn_frame: self.frame_count,
+                        spawn_y: (CACTUS_Y as i32 + self.settings.ground_offset()).into(),
+                    },
+                    EnemyKind::Spring => Enemy {
+                        kind: EnemyKind::Spring,
+                        position: (spawn_x, SPRING_Y as i32 + self.settings.ground_offset()).into(),
+                        triggered: false,
+                        is_fast: false,
+                        grazed: false,
+                        sway_phase: 0,
+                        spawn_frame: self.frame_count,
+                        spawn_y: (SPRING_Y as i32 + self.settings.ground_offset()).into(),
+                    },
+                    EnemyKind::Spike => Enemy {
+                        kind: EnemyKind::Spike,
+                        position: (spawn_x, SPIKE_Y as i32 + self.settings.ground_offset()).into(),
+                        triggered: false,
+                        is_fast: false,
+                        grazed: false,
+                        sway_phase: 0,
+                        spawn_frame: self.frame_count,
+                        spawn_y: (SPIKE_Y as i32 + self.settings.ground_offset()).into(),
+                    },
+                };
+                spawn_x += self.min_spawn_gap_px(&enemy);
+                self.enemies.push_back(enemy);
+            }
+        }
+
+        // Calc enemies' position and collision detection
+        let dino_collision_rect = self.player.collision_rect();
+        let player_collision_rect = placed_rect(dino_collision_rect, self.player.position);
+        // Growing the dino's box rather than each enemy's finds the same grazes
+        let mut near_miss_rect = dino_collision_rect;
+        near_miss_rect.position -= (NEAR_MISS_MARGIN_PX, NEAR_MISS_MARGIN_PX).into();
+        near_miss_rect.size += (NEAR_MISS_MARGIN_PX * 2, NEAR_MISS_MARGIN_PX * 2).into();
+        // Move coins, pulling nearby ones towards the dino while the magnet lasts, and pick up
+        // the ones touching it
+        self.magnet_frames = self.magnet_frames.saturating_sub(1);
+        let player_center = self.player.position + (16, 16).into();
+        for coin in self.collectibles.iter_mut() {
+            coin.position.x -= scroll_step;
+
+            let to_player = player_center - (coin.position + (4, 4).into());
+            if self.magnet_frames > 0
+                && coin.kind == CollectibleKind::Coin
+                && to_player.x.abs().floor() < MAGNET_RADIUS_PX
+                && to_player.y.abs().floor() < MAGNET_RADIUS_PX
+            {
+                coin.pull_speed += MAGNET_PULL_ACCELERATION;
+                // Already on the dino's center, it gets picked up below anyway
+                if to_player.fast_magnitude() < Number::new(1) {
+                    continue;
+                }
+                coin.position += to_player.fast_normalise() * (coin.pull_speed * self.delta_frames);
+            } else {
+                coin.pull_speed = Number::new(0);
+            }
+        }
+        let coin_rect = COIN_COLLISION_RECT;
+        let mut collected: u32 = 0;
+        let mut is_magnet_collected = false;
+        let mut is_feather_collected = false;
+        let mut is_snowflake_collected = false;
+        self.collectibles.retain(|coin| {
+            if placed_rect(coin_rect, coin.position).touches(player_collision_rect) {
+                match coin.kind {
+                    CollectibleKind::Coin => collected += 1,
+                    CollectibleKind::Magnet => is_magnet_collected = true,
+                    CollectibleKind::Feather => is_feather_collected = true,
+                    CollectibleKind::Snowflake => is_snowflake_collected = true,
+                }
+                return false;
+            }
+            coin.position.x.floor() >= -8
+        });
+        if collected > 0 || is_magnet_collected || is_feather_collected || is_snowflake_collected {
+            sink.push(GameEvent::CoinCollected);
+            self.coins_collected += collected;
+            self.bonus_score += COIN_BONUS_SCORE * collected;
+        }
+        if is_magnet_collected {
+            self.magnet_frames = MAGNET_DURATION_FRAMES;
+        }
+        if is_feather_collected {
+            self.low_gravity_frames = LOW_GRAVITY_DURATION_FRAMES;
+        }
+        if is_snowflake_collected {
+            self.freeze_frames = FREEZE_DURATION_FRAMES;
+        }
+
+        self.invulnerable_frames = self.invulnerable_frames.saturating_sub(1);
+        self.hit_flash_frames = self.hit_flash_frames.saturating_sub(1);
+        let mut is_collided: bool = false;
+        let mut is_launched: bool = false;
+        let mut is_near_miss: bool = false;
+        for enemy in self.enemies.iter_mut() {
+            if enemy.is_fast {
+                enemy.position.x -=
+                    self.enemy_velocity * FAST_ENEMY_SPEED_RATIO * self.delta_frames;
+            } else {
+                enemy.position.x -= enemy_step;
+            }
+            if enemy.kind == EnemyKind::Bird && self.settings.bird_bob {
+                let t = Number::new(self.frame_count.wrapping_sub(enemy.spawn_frame) as i32)
+                    / BIRD_BOB_PERIOD_FRAMES;
+                enemy.position.y = enemy.spawn_y + t.sin() * BIRD_BOB_PX;
+            }
+
+            // Collision detection
+            // The grown box is the widest the dino gets, so it gates the graze check too
+            let enemy_collision_rect = enemy.kind.collision_rect();
+            if is_in_collision_window(
+                near_miss_rect,
+                enemy_collision_rect,
+                self.player.position,
+                enemy.position,
+            ) {
+                // Passing straight through during the invulnerability isn't a close call
+                if enemy.kind != EnemyKind::Spring
+                    && self.invulnerable_frames == 0
+                    && check_collision(
+                        near_miss_rect,
+                        enemy_collision_rect,
+                        self.player.position,
+                        enemy.position,
+                    )
+                {
+                    enemy.grazed = true;
+                }
+
+                // Springs are meant to be touched, so only the hazards get the grace
+                let (player_rect, enemy_rect) = if enemy.kind == EnemyKind::Spring {
+                    (dino_collision_rect, enemy_collision_rect)
+                } else {
+                    let grace_px = self.settings.collision_grace_px;
+                    (
+                        shrunk_rect(dino_collision_rect, grace_px),
+                        shrunk_rect(enemy_collision_rect, grace_px),
+                    )
+                };
+                if check_collision(
+                    player_rect,
+                    enemy_rect,
+                    self.player.position,
+                    enemy.position,
+                ) {
+                    match enemy.kind {
+                        EnemyKind::Spring => {
+                            // Only a grounded dino is launched, one passing over it mid-air
+                            // leaves it set for the next landing
+                            if !enemy.triggered && !self.player.is_jumping {
+                                enemy.triggered = true;
+                                is_launched = true;
+                            }
+                        }
+                        _ if self.invulnerable_frames > 0 => {}
+                        _ => {
+                            print_info(&mut self.mgba, format_args!("collide: {:?}", enemy.kind));
+                            is_collided = true;
+                        }
+                    }
+                }
+            } else if enemy.grazed && enemy.position.x < self.player.position.x {
+                // Grazed and now behind the dino, so it was cleared by a hair
+                enemy.grazed = false;
+                is_near_miss = true;
+                sink.push(GameEvent::NearMiss);
+            }
+        }
+        self.slow_motion_cooldown_frames = self.slow_motion_cooldown_frames.saturating_sub(1);
+        if is_near_miss {
+            self.near_misses += 1;
+        }
+        if is_near_miss
+            && !is_collided
+            && !self.settings.reduced_flashing
+            && self.slow_motion_cooldown_frames == 0
+        {
+            print_info(&mut self.mgba, format_args!("near miss"));
+            self.slow_motion_frames = SLOW_MOTION_FRAMES;
+            self.slow_motion_cooldown_frames = SLOW_MOTION_COOLDOWN_FRAMES;
+        }
+        #[cfg(debug_assertions)]
+        if is_collided {
+            self.dump_trace();
+        }
+        if is_collided {
+            self.combo = 1;
+        }
+        if is_collided && self.lives_left > 0 {
+            // Spend a life and clear the field so the dino isn't hit again straight away, and
+            // knock it back with a short hop so the hit reads. The usual x clamp keeps it on
+            // screen and it walks back to its spot once landed.
+            sink.push(GameEvent::LifeLost);
+            self.lives_left -= 1;
+            self.enemies.clear();
+            self.invulnerable_frames = INVULNERABLE_FRAMES;
+            if !self.settings.reduced_flashing {
+                self.hit_flash_frames = HIT_FLASH_FRAMES;
+            }
+            self.player.vertical_speed = -self.jump_speed() * KNOCKBACK_LIFT;
+            self.player.knockback_speed = -KNOCKBACK_SPEED;
+            self.player.is_jumping = true;
+            self.player.landing_frames = 0;
+            self.player.can_cut_jump = false;
+        } else if is_collided {
+            sink.push(GameEvent::Over);
+            // Only a record that replaces an earlier one is worth celebrating
+            self.is_new_hi_score =
+                self.settings.hi_score > 0 && self.current_score() > self.settings.hi_score;
+            self.set_state(GameState::Over(self.current_score()));
+        } else if is_launched {
+            sink.push(GameEvent::SpringLaunch);
+            // 1.25x the jump speed, so roughly 1.5x the jump height
+            self.player.vertical_speed = -self.jump_speed() * num!(1.25);
+            self.player.is_jumping = true;
+            self.player.landing_frames = 0;
+        }
+
+        // Remove enemies once they're fully off the left edge, wherever they are in the queue as
+        // fast birds overtake the rest. Each is removed exactly once and a hit clears the field,
+        // so everything leaving here was dodged; springs are there to be used.
+        self.enemies.retain(|enemy| {
+            if enemy.position.x.floor() + enemy.kind.width() > 0 {
+                return true;
+            }
+            if enemy.kind != EnemyKind::Spring {
+                sink.push(GameEvent::EnemyDodged { kind: enemy.kind });
+                self.enemies_dodged += 1;
+                self.bonus_score += DODGE_BONUS_SCORE * self.combo;
+                self.combo = (self.combo + 1).min(self.settings.max_combo.max(1));
+                self.frames_since_dodge = 0;
+            }
+            false
+        });
+        self.frames_since_dodge += 1;
+        if self.frames_since_dodge >= COMBO_DECAY_FRAMES {
+            self.combo = (self.combo - 1).max(1);
+            self.frames_since_dodge = 0;
+        }
+
+        self.background_position.x += scroll_step;
+
+        // A life for every milestone passed, even several at once from a coin bonus
+        self.life_pulse_frames = self.life_pulse_frames.saturating_sub(1);
+        if self.settings.extra_life_interval > 0 && self.state == GameState::Continue {
+            let milestone = self.current_score() / self.settings.extra_life_interval;
+            if milestone > self.last_life_milestone {
+                let earned = milestone - self.last_life_milestone;
+                self.last_life_milestone = milestone;
+                let lives = (self.lives_left as u32 + earned).min(MAX_LIVES as u32) as u8;
+                if lives > self.lives_left {
+                    self.lives_left = lives;
+                    self.life_pulse_frames = LIFE_PULSE_FRAMES;
+                    sink.push(GameEvent::ExtraLife);
+                }
+            }
+        }
+
+        // Achievements, toasting the newest one. Unranked runs can't earn any.
+        self.toast_frames = self.toast_frames.saturating_sub(1);
+        for achievement in Achievement::ALL {
+            let is_met = self.settings.is_ranked
+                && match achievement {
+                    Achievement::Level10 => self.speed_level >= 10,
+                    Achievement::Coins1000 => {
+                        self.settings.coin_balance + self.coins_collected >= 1000
+                    }
+                    Achievement::Survive5Minutes => self.frame_count >= 60 * 60 * 5,
+                    Achievement::NearMisses10 => self.near_misses >= 10,
+                };
+            if is_met && self.achievements & achievement.mask() == 0 {
+                print_info(
+                    &mut self.mgba,
+                    format_args!("achievement: {:?}", achievement),
+                );
+                self.achievements |= achievement.mask();
+                self.toast = Some(achievement);
+                self.toast_frames = TOAST_FRAMES;
+                sink.push(GameEvent::AchievementUnlocked(achievement));
+            }
+        }
+        self.state
+    }
+
+    // Off to a fresh run behind the wipe, or straight away when it's off to the results instead
+    fn restart(&mut self) {
+        let is_last_turn = self.settings.players > 1 && self.turn + 1 == self.settings.players;
+        if is_last_turn {
+            self.set_state(GameState::Restart);
+        } else {
+            self.wipe_frames = WIPE_FRAMES;
+        }
+    }
+
+    // The restart wipe is the backdrop showing through window 0, which covers the screen from
+    // the left while the old run is up and then uncovers the new one from the left too
+    pub fn render_wipe(&self, windows: &mut Windows) {
+        let half = WIPE_FRAMES / 2;
+        let covered = if self.wipe_frames > half {
+            WIPE_FRAMES - self.wipe_frames
+        } else {
+            self.wipe_frames
+        };
+        let width = WIDTH * covered as i32 / half as i32;
+        let window = windows.win_in(WinIn::Win0);
+        if width == 0 {
+            window.disable();
+            return;
+        }
+        let x = match self.state {
+            GameState::Over(_) | GameState::Restart => 0,
+            _ => WIDTH - width,
+        };
+        window
+            .enable()
+            .set_position(&Rect::new((x, 0).into(), (width, HEIGHT).into()));
+    }
+
+    // Every `?` gives up on the rest of the frame once OAM is full, so objects go in by how much
+    // they matter, which is also front to back:
+    // 1. the dino, enemies, the fast enemy warning and coins, which decide the run
+    // 2. the HUD: scores, time, distance, hints, toasts, lives and power-ups
+    // 3. the pause and game over overlays
+    // 4. decoration: the moon and stars, then particles
+    // Anything new should slot into this order rather than go at the end.
+    pub fn render(
+        &mut self,
+        oam_frame: &mut OamIterator,
+        sprite_cache: &SpriteCache,
+    ) -> Option<()> {
+        // Draw player
+        let jump_frame = DINO_JUMP_FRAME.min(sprite_cache.dino.len() - 1);
+        let sprite = match self.state {
+            GameState::Over(_) => match &sprite_cache.overlay {
+                Some(overlay) => overlay.dino_over.clone(),
+                None => sprite_cache.dino[jump_frame].clone(),
+            },
+            _ => {
+                // The hit frames follow the same run cycle, so the tint can come and go on
+                // any frame. There are no tinted ducking frames, so the flash stands the dino
+                // up for its few frames rather than go missing.
+                let frames = if self.hit_flash_frames > 0 {
+                    &sprite_cache.dino_hit
+                } else if self.player.is_ducking {
+                    &sprite_cache.dino_duck
+                } else {
+                    &sprite_cache.dino
+                };
+                if self.player.is_jumping {
+                    frames[jump_frame].clone()
+                } else {
+                    let index = self.animation_frame(frames.len());
+                    frames[index].clone()
+                }
+            }
+        };
+        let mut player_object = ObjectUnmanaged::new(sprite);
+        player_object
+            .show()
+            .set_position(self.player.position.floor());
+        if self.player.landing_frames > 0 && self.state == GameState::Continue {
+            // Squash then rebound. Only the drawn sprite is scaled, the collision rect is untouched.
+            let scale: Vector2D<Number> = if self.player.landing_frames > LANDING_SQUASH_FRAMES / 2
+            {
+                (num!(1.2), num!(0.8)).into()
+            } else {
+                (num!(0.95), num!(1.05)).into()
+            };
+            let matrix = AffineMatrix::from_scale(
+                (Number::new(1) / scale.x, Number::new(1) / scale.y).into(),
+            );
+            // Double size affine objects are offset by half a sprite, and the feet are kept
+            // on the ground by shifting down by however much the bottom half shrank
+            let feet_offset = ((Number::new(1) - scale.y) * 16).floor();
+            player_object
+                .set_affine_matrix(AffineMatrixInstance::new(matrix.to_object_wrapping()))
+                .show_affine(AffineMode::AffineDouble)
+                .set_position(self.player.position.floor() + (-16, feet_offset - 16).into());
+        } else if self.lean_frames > 0
+            && !self.player.is_jumping
+            && self.state == GameState::Continue
+            && !self.settings.reduced_flashing
+        {
+            // Tilted forward about its centre, drawn only, like the squash. The matrix maps
+            // the screen onto the sprite, so a clockwise lean is a negative angle.
+            let angle = LEAN_MAX_ANGLE * self.lean_frames as i32 / LEAN_FRAMES as i32;
+            let matrix = AffineMatrix::from_rotation(-angle);
+            player_object
+                .set_affine_matrix(AffineMatrixInstance::new(matrix.to_object_wrapping()))
+                .show_affine(AffineMode::AffineDouble)
+                .set_position(self.player.position.floor() + (-16, -16).into());
+        }
+        // Tinted red straight after the hit, then blinks for the rest of the invulnerability.
+        // Under reduced flashing it does neither and just stays drawn.
+        if self.hit_flash_frames > 0
+            || self.invulnerable_frames / 4 % 2 == 0
+            || self.settings.reduced_flashing
+        {
+            oam_frame.next()?.set(&player_object);
+        }
+
+        // Draw enemy
+        for enemy in self.enemies.iter() {
+            let sprite = match enemy.kind {
+                EnemyKind::Bird => {
+                    sprite_cache.bird[self.bird_flap_frame(sprite_cache.bird.len())].clone()
+                }
+                EnemyKind::Cactus(size) => {
+                    let frames = match size {
+                        CactusSize::Small => &sprite_cache.cactus_small,
+                        CactusSize::Normal => &sprite_cache.cactus,
+                        CactusSize::Tall => &sprite_cache.cactus_tall,
+                    };
+                    let step = frame_ranger(
+                        self.frame_count.wrapping_add(enemy.sway_phase),
+                        0,
+                        CACTUS_SWAY_SEQUENCE.len() as u32 - 1,
+                        CACTUS_SWAY_FRAMES,
+                    );
+                    frames[CACTUS_SWAY_SEQUENCE[step]].clone()
+                }
+                EnemyKind::Spring => sprite_cache
+                    .spring
+                    .get(enemy.triggered as usize)
+                    .unwrap()
+                    .clone(),
+                EnemyKind::Spike => sprite_cache.spike.clone(),
+            };
+            let mut object = ObjectUnmanaged::new(sprite);
+            object.show().set_position(enemy.position.floor());
+            oam_frame.next()?.set(&object);
+        }
+
+        // Warn about a fast enemy at the edge it's about to come in from, until it's spawned
+        if let Some((spawn_info, frames_to_spawn)) = self.upcoming_spawns().next() {
+            if spawn_info.enemy_kind() == EnemyKind::Bird
+                && spawn_info.is_fast()
+                && self.has_room_for_enemy()
+                && frames_to_spawn <= self.settings.fast_enemy_warning_frames
+                && self.frame_count % 16 < 10
+            {
+                let mut object = ObjectUnmanaged::new(sprite_cache.warning.clone());
+                object.show().set_position(
+                    (
+                        230,
+                        spawn_info.bird_y() + self.settings.ground_offset() + 12,
+                    )
+                        .into(),
+                );
+                oam_frame.next()?.set(&object);
+            }
+        }
+
+        // Draw coins, spinning in step with each other
+        let coin_index = frame_ranger(self.frame_count, 0, sprite_cache.coin.len() as u32 - 1, 6);
+        for coin in self.collectibles.iter() {
+            let sprite = match coin.kind {
+                CollectibleKind::Coin => sprite_cache.coin[coin_index].clone(),
+                CollectibleKind::Magnet => sprite_cache.magnet.clone(),
+                CollectibleKind::Feather => sprite_cache.feather.clone(),
+                CollectibleKind::Snowflake => sprite_cache.snowflake.clone(),
+            };
+            let mut object = ObjectUnmanaged::new(sprite);
+            object.show().set_position(coin.position.floor());
+            oam_frame.next()?.set(&object);
+        }
+
+        // Draw score
+        let hud = &self.settings.hud;
+        let score = self.current_score();
+        draw_score_digits(
+            score,
+            false,
+            hud.score,
+            oam_frame,
+            sprite_cache,
+            TextAlign::Right,
+        );
+        draw_str(
+            "SCORE",
+            hud.score + (-7 * 6 - 2, 1).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Right,
+        );
+
+        // Draw hi score
+        draw_score_digits(
+            self.settings.hi_score,
+            false,
+            hud.hi_score,
+            oam_frame,
+            sprite_cache,
+            TextAlign::Right,
+        );
+        draw_str(
+            "HI",
+            hud.hi_score + (-7 * 6 - 2, 1).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Right,
+        );
+
+        // Draw the previous run's score under them to compare against once this one is over
+        if matches!(self.state, GameState::Over(_)) && self.settings.last_score > 0 {
+            draw_score_digits(
+                self.settings.last_score,
+                false,
+                hud.last_score,
+                oam_frame,
+                sprite_cache,
+                TextAlign::Right,
+            );
+            draw_str(
+                "LAST",
+                hud.last_score + (-7 * 6 - 2, 1).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Right,
+            );
+        }
+
+        // Draw elapsed time (frame_count doesn't advance while paused) and distance
+        if self.settings.show_time {
+            draw_time(
+                self.frame_count / 60,
+                hud.time,
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+        }
+        draw_distance(
+            self.distance_m(),
+            hud.distance,
+            oam_frame,
+            sprite_cache,
+            TextAlign::Left,
+        );
+
+        if let Some(step) = self
+            .tutorial_step
+            .checked_sub(1)
+            .filter(|_| self.tutorial_hint_frames > 0)
+        {
+            draw_str(
+                TUTORIAL[step].hint,
+                (120, 36).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Center,
+            );
+        }
+
+        if let Some(achievement) = self.toast.filter(|_| self.toast_frames > 0) {
+            draw_str(
+                achievement.name(),
+                (120, 52).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Center,
+            );
+        }
+
+        // Draw the spare lives, hopping for a moment when one is earned
+        if self.lives_left > 0 {
+            let hop = if (self.life_pulse_frames / 6) % 2 == 1 {
+                -2
+            } else {
+                0
+            };
+            let mut object = ObjectUnmanaged::new(sprite_cache.heart.clone());
+            object.show().set_position(hud.lives + (0, hop).into());
+            oam_frame.next()?.set(&object);
+            draw_number(
+                self.lives_left as u32,
+                1,
+                false,
+                hud.lives + (10, 0).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+        }
+
+        // Draw the power-ups' remaining seconds, blinking through their last two
+        for (frames_left, sprite, x) in [
+            (self.magnet_frames, &sprite_cache.magnet, 0),
+            (self.low_gravity_frames, &sprite_cache.feather, 24),
+            (self.freeze_frames, &sprite_cache.snowflake, 48),
+        ] {
+            if frames_left == 0 || (frames_left <= 120 && self.frame_count % 20 >= 14) {
+                continue;
+            }
+            let mut object = ObjectUnmanaged::new(sprite.clone());
+            object.show().set_position(hud.power_ups + (x, 0).into());
+            oam_frame.next()?.set(&object);
+            draw_number(
+                frames_left.div_ceil(60),
+                1,
+                false,
+                hud.power_ups + (x + 10, 0).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+        }
+
+        // A hit resets the combo, so it's never up on the game over screen
+        if self.combo > 1 {
+            draw_number(
+                self.combo,
+                1,
+                false,
+                hud.combo,
+                oam_frame,
+                sprite_cache,
+                TextAlign::Right,
+            );
+            let digits = self.combo.ilog10() as i32 + 1;
+            draw_str(
+                "X",
+                hud.combo - (GLYPH_WIDTH * digits, 0).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Right,
+            );
+        }
+
+        if self.resume_countdown_frames > 0 {
+            draw_number(
+                self.resume_countdown_frames.div_ceil(RESUME_COUNT_FRAMES),
+                1,
+                false,
+                (120, 60).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Center,
+            );
+        }
+
+        if self.quit_hold_frames >= QUIT_HINT_FRAMES {
+            draw_str(
+                "HOLD TO QUIT",
+                (120, 90).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Center,
+            );
+        }
+
+        match self.state {
+            GameState::Over(score) => {
+                if self.is_new_hi_score {
+                    if self.settings.reduced_flashing {
+                        draw_str(
+                            "NEW HIGH SCORE!",
+                            (120, 44).into(),
+                            oam_frame,
+                            sprite_cache,
+                            TextAlign::Center,
+                        );
+                    } else {
+                        draw_str_blinking(
+                            "NEW HIGH SCORE!",
+                            (120, 44).into(),
+                            30,
+                            20,
+                            self.frames_in_state,
+                            oam_frame,
+                            sprite_cache,
+                            TextAlign::Center,
+                        );
+                    }
+                }
+                if self.settings.reduced_flashing {
+                    draw_str(
+                        "G A M E  O V E R",
+                        (120, 60).into(),
+                        oam_frame,
+                        sprite_cache,
+                        TextAlign::Center,
+                    );
+                } else {
+                    draw_str_dropping(
+                        "G A M E  O V E R",
+                        (120, 60).into(),
+                        self.frames_in_state,
+                        oam_frame,
+                        sprite_cache,
+                        TextAlign::Center,
+                    );
+                }
+                draw_number(
+                    score,
+                    1,
+                    true,
+                    (120, 72).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Center,
+                );
+                let mut object = ObjectUnmanaged::new(sprite_cache.coin[0].clone());
+                object.show().set_position((84, 84).into());
+                oam_frame.next()?.set(&object);
+                draw_number(
+                    self.coins_collected,
+                    1,
+                    false,
+                    (94, 84).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Left,
+                );
+                draw_distance(
+                    self.distance_m(),
+                    (156, 84).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Right,
+                );
+                if self.restart_confirm_frames > 0 {
+                    draw_str(
+                        "RESTART? PRESS A AGAIN",
+                        (120, 98).into(),
+                        oam_frame,
+                        sprite_cache,
+                        TextAlign::Center,
+                    );
+                } else if self.settings.players > 1 {
+                    let is_last_turn = self.turn + 1 == self.settings.players;
+                    // Numbered from 1 for whoever goes next, a single digit being plenty
+                    let mut next_turn = *b"PLAYER 0'S TURN";
+                    next_turn[7] += self.turn + 2;
+                    draw_str(
+                        if is_last_turn {
+                            "PRESS A FOR RESULTS"
+                        } else {
+                            core::str::from_utf8(&next_turn).unwrap_or_default()
+                        },
+                        (120, 98).into(),
+                        oam_frame,
+                        sprite_cache,
+                        TextAlign::Center,
+                    );
+                } else {
+                    draw_str_blinking(
+                        "PRESS A TO RESTART",
+                        (120, 98).into(),
+                        60,
+                        40,
+                        self.frames_in_state,
+                        oam_frame,
+                        sprite_cache,
+                        TextAlign::Center,
+                    );
+                    draw_str(
+                        "SELECT: SHOP",
+                        (120, 110).into(),
+                        oam_frame,
+                        sprite_cache,
+                        TextAlign::Center,
+                    );
+                }
+
+                // Recap of the run over the ground, coins and distance being shown above. OAM is
+                // nearly full on this screen, so it turns over a pair of numbers at a time.
+                let recap = [
+                    ("JUMPS", self.jumps),
+                    ("DODGED", self.enemies_dodged),
+                    ("NEAR MISSES", self.near_misses),
+                    ("TOP LEVEL", self.speed_level as u32),
+                ];
+                let page = (self.frames_in_state / RECAP_PAGE_FRAMES) as usize % 2;
+                for (column, (label, value)) in recap[page * 2..][..2].iter().enumerate() {
+                    let x = 16 + column as i32 * 112;
+                    let y = 128;
+                    draw_str(
+                        label,
+                        (x, y).into(),
+                        oam_frame,
+                        sprite_cache,
+                        TextAlign::Left,
+                    );
+                    draw_number(
+                        *value,
+                        1,
+                        false,
+                        (x + 96, y).into(),
+                        oam_frame,
+                        sprite_cache,
+                        TextAlign::Right,
+                    );
+                }
+            }
+            GameState::Pause => {
+                draw_str(
+                    "P A U S E D",
+                    (120, 60).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Center,
+                );
+                draw_str_blinking(
+                    "PRESS START TO RESUME",
+                    (120, 75).into(),
+                    30,
+                    15,
+                    self.frames_in_state,
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Center,
+                );
+                draw_str(
+                    "SELECT: OPTIONS",
+                    (120, 110).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Center,
+                );
+            }
+            _ => {}
+        }
+
+        // Draw the night sky behind the foreground, stars appearing one by one as night falls
+        if let Some(frames_into_night) = self.frames_into_night() {
+            let mut object = ObjectUnmanaged::new(sprite_cache.moon.clone());
+            object.show().set_position(MOON_POSITION.into());
+            oam_frame.next()?.set(&object);
+
+            for (index, position) in STAR_POSITIONS.iter().enumerate() {
+                if frames_into_night < index as u32 * 10 {
+                    break;
+                }
+                let twinkle = frame_ranger(
+                    self.frame_count + index as u32 * 7,
+                    0,
+                    sprite_cache.stars.len() as u32 - 1,
+                    30,
+                );
+                let mut object = ObjectUnmanaged::new(sprite_cache.stars[twinkle].clone());
+                object.show().set_position((*position).into());
+                oam_frame.next()?.set(&object);
+            }
+        }
+
+        // Draw particles last so they're the first thing dropped when OAM runs out
+        for particle in self.particles.iter() {
+            // Animated over their lifetime, except confetti which keeps its colour
+            let (sprites, lifetime_frames) = match particle.kind {
+                ParticleKind::Dust => (&sprite_cache.dust, DUST_LIFETIME_FRAMES),
+                ParticleKind::Sparkle => (&sprite_cache.stars, SPARKLE_LIFETIME_FRAMES),
+                ParticleKind::Gust => (&sprite_cache.dust, GUST_PARTICLE_LIFETIME_FRAMES),
+                ParticleKind::Confetti { .. } => (&sprite_cache.confetti, CONFETTI_LIFETIME_FRAMES),
+            };
+            let sprite_index = match particle.kind {
+                ParticleKind::Confetti { colour } => colour % sprites.len(),
+                _ => {
+                    let age = (lifetime_frames - particle.frames_left) as usize;
+                    age * sprites.len() / lifetime_frames as usize
+                }
+            };
+            let mut object = ObjectUnmanaged::new(sprites[sprite_index].clone());
+            object.show().set_position(particle.position.floor());
+            oam_frame.next()?.set(&object);
+        }
+
+        Some(())
+    }
+}
+
+pub enum ShopEvent {
+    None,
+    Purchased,
+    Exit,
+}
+
+struct ShopItem {
+    unlock: Unlock,
+    name: &'static str,
+    price: u32,
+}
+
+const SHOP_ITEMS: [ShopItem; 2] = [
+    ShopItem {
+        unlock: Unlock::RedSkin,
+        name: "RED DINO",
+        price: 100,
+    },
+    ShopItem {
+        unlock: Unlock::ExtraLife,
+        name: "EXTRA LIFE",
+        price: 200,
+    },
+];
+
+// Spends the lifetime coin balance on permanent unlocks. It only edits the save buffer,
+// writing it out is left to the caller on `ShopEvent::Purchased`.
+pub struct Shop {
+    input: ButtonController,
+    cursor: usize,
+    frame_count: u32,
+}
+
+impl Default for Shop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shop {
+    pub fn new() -> Self {
+        Self {
+            input: ButtonController::new(),
+            cursor: 0,
+            frame_count: 0,
+        }
+    }
+
+    pub fn frame(&mut self, save_buffer: &mut SaveBuffer, mixer: &mut Mixer<'_>) -> ShopEvent {
+        self.input.update();
+        self.frame_count += 1;
+
+        if self.input.is_just_pressed(Button::B) || self.input.is_just_pressed(Button::START) {
+            return ShopEvent::Exit;
+        }
+        if self.input.is_just_pressed(Button::UP) {
+            self.cursor = (self.cursor + SHOP_ITEMS.len() - 1) % SHOP_ITEMS.len();
+        }
+        if self.input.is_just_pressed(Button::DOWN) {
+            self.cursor = (self.cursor + 1) % SHOP_ITEMS.len();
+        }
+
+        if self.input.is_just_pressed(Button::A) {
+            let item = &SHOP_ITEMS[self.cursor];
+            if !save_buffer.is_unlocked(item.unlock) && save_buffer.spend_coins(item.price) {
+                save_buffer.unlock(item.unlock);
+                play_sound(mixer, SoundEffectKind::Coin);
+                return ShopEvent::Purchased;
+            }
+        }
+        ShopEvent::None
+    }
+
+    pub fn render(
+        &self,
+        oam_frame: &mut OamIterator,
+        sprite_cache: &SpriteCache,
+        save_buffer: &SaveBuffer,
+    ) -> Option<()> {
+        draw_str(
+            "S H O P",
+            (120, 40).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+
+        // Balance
+        let mut object = ObjectUnmanaged::new(sprite_cache.coin[0].clone());
+        object.show().set_position((100, 54).into());
+        oam_frame.next()?.set(&object);
+        draw_number(
+            save_buffer.get_coins(),
+            1,
+            true,
+            (110, 54).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Left,
+        );
+
+        for (index, item) in SHOP_ITEMS.iter().enumerate() {
+            let y = 72 + index as i32 * 14;
+            if index == self.cursor {
+                let coin_index =
+                    frame_ranger(self.frame_count, 0, sprite_cache.coin.len() as u32 - 1, 6);
+                let mut object = ObjectUnmanaged::new(sprite_cache.coin[coin_index].clone());
+                object.show().set_position((28, y).into());
+                oam_frame.next()?.set(&object);
+            }
+            draw_str(
+                item.name,
+                (40, y).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+            if save_buffer.is_unlocked(item.unlock) {
+                draw_str(
+                    "OWNED",
+                    (212, y).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Right,
+                );
+            } else {
+                draw_number(
+                    item.price,
+                    1,
+                    false,
+                    (212, y).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Right,
+                );
+            }
+        }
+
+        draw_str(
+            "A: BUY  B: EXIT",
+            (120, 110).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        Some(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OptionsEvent {
+    None,
+    Changed,
+    Exit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OptionsItem {
+    Difficulty,
+    Skin,
+    Rumble,
+    ReducedFlashing,
+}
+
+impl OptionsItem {
+    const ALL: [OptionsItem; 4] = [
+        OptionsItem::Difficulty,
+        OptionsItem::Skin,
+        OptionsItem::Rumble,
+        OptionsItem::ReducedFlashing,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            OptionsItem::Difficulty => "DIFFICULTY",
+            OptionsItem::Skin => "SKIN",
+            OptionsItem::Rumble => "RUMBLE",
+            OptionsItem::ReducedFlashing => "REDUCED FLASHING",
+        }
+    }
+}
+
+// Every preference in one place, reachable from the title and the pause screen. Changes go
+// straight into the save buffer so they show at once, and writing it out is left to the caller
+// once the menu is closed, like the shop.
+pub struct Options {
+    input: ButtonController,
+    cursor: usize,
+    frame_count: u32,
+    // Only offered from the title, a run keeps the difficulty it started with
+    difficulty: Option<Difficulty>,
+}
+
+impl Options {
+    pub fn new(difficulty: Option<Difficulty>) -> Self {
+        Self {
+            input: ButtonController::new(),
+            cursor: 0,
+            frame_count: 0,
+            difficulty,
+        }
+    }
+
+    pub fn difficulty(&self) -> Option<Difficulty> {
+        self.difficulty
+    }
+
+    fn items(&self) -> &'static [OptionsItem] {
+        if self.difficulty.is_some() {
+            &OptionsItem::ALL
+        } else {
+            &OptionsItem::ALL[1..]
+        }
+    }
+
+    pub fn frame(&mut self, save_buffer: &mut SaveBuffer) -> OptionsEvent {
+        self.input.update();
+        self.frame_count += 1;
+
+        // Not START, whose press would carry on to the title or pause screen underneath and
+        // start or resume the run
+        if self.input.is_just_pressed(Button::B) {
+            return OptionsEvent::Exit;
+        }
+        let items = self.items();
+        if self.input.is_just_pressed(Button::UP) {
+            self.cursor = (self.cursor + items.len() - 1) % items.len();
+        }
+        if self.input.is_just_pressed(Button::DOWN) {
+            self.cursor = (self.cursor + 1) % items.len();
+        }
+
+        // A and RIGHT step a value forwards, LEFT back, which for a switch is the same thing
+        let forwards =
+            self.input.is_just_pressed(Button::A) || self.input.is_just_pressed(Button::RIGHT);
+        if !forwards && !self.input.is_just_pressed(Button::LEFT) {
+            return OptionsEvent::None;
+        }
+        let preference = match items[self.cursor] {
+            OptionsItem::Difficulty => {
+                self.difficulty = self.difficulty.map(|difficulty| {
+                    if forwards {
+                        difficulty.harder()
+                    } else {
+                        difficulty.easier()
+                    }
+                });
+                return OptionsEvent::Changed;
+            }
+            OptionsItem::Skin if !save_buffer.is_unlocked(Unlock::RedSkin) => {
+                return OptionsEvent::None;
+            }
+            OptionsItem::Skin => Preference::DefaultSkin,
+            OptionsItem::Rumble => Preference::Rumble,
+            OptionsItem::ReducedFlashing => Preference::ReducedFlashing,
+        };
+        save_buffer.set_preference(preference, !save_buffer.is_preferred(preference));
+        OptionsEvent::Changed
+    }
+
+    pub fn render(
+        &self,
+        oam_frame: &mut OamIterator,
+        sprite_cache: &SpriteCache,
+        save_buffer: &SaveBuffer,
+    ) -> Option<()> {
+        draw_str(
+            "O P T I O N S",
+            (120, 32).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+
+        let on_off = |preference| {
+            if save_buffer.is_preferred(preference) {
+                "ON"
+            } else {
+                "OFF"
+            }
+        };
+        for (index, item) in self.items().iter().enumerate() {
+            let y = 56 + index as i32 * 14;
+            if index == self.cursor {
+                let coin_index =
+                    frame_ranger(self.frame_count, 0, sprite_cache.coin.len() as u32 - 1, 6);
+                let mut object = ObjectUnmanaged::new(sprite_cache.coin[coin_index].clone());
+                object.show().set_position((16, y).into());
+                oam_frame.next()?.set(&object);
+            }
+            draw_str(
+                item.name(),
+                (28, y).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+            let value = match item {
+                OptionsItem::Difficulty => self.difficulty.unwrap_or(Difficulty::Normal).name(),
+                OptionsItem::Skin if !save_buffer.is_unlocked(Unlock::RedSkin) => "LOCKED",
+                OptionsItem::Skin if save_buffer.is_preferred(Preference::DefaultSkin) => "GREEN",
+                OptionsItem::Skin => "RED",
+                OptionsItem::Rumble => on_off(Preference::Rumble),
+                OptionsItem::ReducedFlashing => on_off(Preference::ReducedFlashing),
+            };
+            draw_str(
+                value,
+                (224, y).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Right,
+            );
+        }
+
+        // The skin as it will look, which the caller swaps in as soon as it changes
+        let mut object = ObjectUnmanaged::new(sprite_cache.dino[0].clone());
+        object.show().set_position((104, 112).into());
+        oam_frame.next()?.set(&object);
+
+        draw_str(
+            "A: CHANGE  B: BACK",
+            (120, 148).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        Some(())
+    }
+}
+
+const CREDITS: &str = "D I N O
+
+PROGRAMMING, GRAPHICS
+AND SOUND BY
+YAMAMOTSU
+
+PALETTE
+DAWNBRINGER 32
+
+BUILT WITH AGB
+THANKS TO ALL
+AGB CONTRIBUTORS
+
+THANKS FOR PLAYING";
+
+// Screens reachable from the title, cycled through with SELECT
+enum TitlePage {
+    Main,
+    Achievements,
+    // Lifetime numbers from the save
+    Stats,
+    // Scrolls up on its own, starting over once the last line is gone
+    Credits {
+        frames: u32,
+    },
+    // Plays each sound effect on demand, to check the samples without a run
+    SoundTest {
+        cursor: usize,
+    },
+    // Debug builds only, starts the stress run
+    #[cfg(debug_assertions)]
+    Debug,
+}
+
+// Picks the difficulty before a run, showing the hi score for the one selected.
+// SELECT cycles through the other pages, see `TitlePage`.
+pub struct Title {
+    input: ButtonController,
+    difficulty: Difficulty,
+    players: u8,
+    tutorial: bool,
+    // See `Settings::calm`
+    calm: bool,
+    // A run quit earlier is stored and can be carried on
+    can_continue: bool,
+    frame_count: u32,
+    page: TitlePage,
+}
+
+pub enum TitleEvent {
+    Start(Difficulty),
+    Continue,
+    Options,
+    #[cfg(debug_assertions)]
+    Stress,
+}
+
+impl Title {
+    pub fn new(difficulty: Difficulty, players: u8, can_continue: bool) -> Self {
+        Self {
+            input: ButtonController::new(),
+            difficulty,
+            players,
+            tutorial: false,
+            calm: false,
+            can_continue,
+            frame_count: 0,
+            page: TitlePage::Main,
+        }
+    }
+
+    // Returns the chosen difficulty once the run should start, or asks for the stored run or
+    // the options menu
+    pub fn frame(&mut self, mixer: &mut Mixer) -> Option<TitleEvent> {
+        self.input.update();
+        self.frame_count += 1;
+
+        if self.input.is_just_pressed(Button::SELECT) {
+            self.page = match self.page {
+                TitlePage::Main => TitlePage::Achievements,
+                TitlePage::Achievements => TitlePage::Stats,
+                TitlePage::Stats => TitlePage::Credits { frames: 0 },
+                TitlePage::Credits { .. } => TitlePage::SoundTest { cursor: 0 },
+                #[cfg(not(debug_assertions))]
+                TitlePage::SoundTest { .. } => TitlePage::Main,
+                #[cfg(debug_assertions)]
+                TitlePage::SoundTest { .. } => TitlePage::Debug,
+                #[cfg(debug_assertions)]
+                TitlePage::Debug => TitlePage::Main,
+            };
+            return None;
+        }
+        if !matches!(self.page, TitlePage::Main)
+            && (self.input.is_just_pressed(Button::B) || self.input.is_just_pressed(Button::START))
+        {
+            self.page = TitlePage::Main;
+            return None;
+        }
+        match &mut self.page {
+            TitlePage::Main => {}
+            TitlePage::Achievements | TitlePage::Stats => return None,
+            TitlePage::Credits { frames } => {
+                *frames += 1;
+                return None;
+            }
+            TitlePage::SoundTest { cursor } => {
+                let sounds = SoundEffectKind::ALL.len();
+                if self.input.is_just_pressed(Button::UP) {
+                    *cursor = (*cursor + sounds - 1) % sounds;
+                }
+                if self.input.is_just_pressed(Button::DOWN) {
+                    *cursor = (*cursor + 1) % sounds;
+                }
+                if self.input.is_just_pressed(Button::A) {
+                    play_sound(mixer, SoundEffectKind::ALL[*cursor]);
+                }
+                return None;
+            }
+            #[cfg(debug_assertions)]
+            TitlePage::Debug if self.input.is_just_pressed(Button::A) => {
+                return Some(TitleEvent::Stress);
+            }
+            #[cfg(debug_assertions)]
+            TitlePage::Debug => return None,
+        }
+
+        if self.input.is_just_pressed(Button::LEFT) {
+            self.difficulty = self.difficulty.easier();
+        }
+        if self.input.is_just_pressed(Button::RIGHT) {
+            self.difficulty = self.difficulty.harder();
+        }
+        // DOWN goes 1 player, 2 players, tutorial, calm and round again, UP the other way
+        let modes = [
+            (1, false, false),
+            (2, false, false),
+            (1, true, false),
+            (1, false, true),
+        ];
+        let mode = modes
+            .iter()
+            .position(|&mode| mode == (self.players, self.tutorial, self.calm))
+            .unwrap_or(0);
+        let mode = if self.input.is_just_pressed(Button::DOWN) {
+            (mode + 1) % modes.len()
+        } else if self.input.is_just_pressed(Button::UP) {
+            (mode + modes.len() - 1) % modes.len()
+        } else {
+            mode
+        };
+        (self.players, self.tutorial, self.calm) = modes[mode];
+        if self.input.is_just_pressed(Button::R) {
+            return Some(TitleEvent::Options);
+        }
+        if self.input.is_just_pressed(Button::L) && self.can_continue {
+            return Some(TitleEvent::Continue);
+        }
+        if self.input.is_just_pressed(Button::START) || self.input.is_just_pressed(Button::A) {
+            return Some(TitleEvent::Start(self.difficulty));
+        }
+        None
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.difficulty = difficulty;
+    }
+
+    pub fn players(&self) -> u8 {
+        self.players
+    }
+
+    pub fn is_tutorial(&self) -> bool {
+        self.tutorial
+    }
+
+    pub fn is_calm(&self) -> bool {
+        self.calm
+    }
+
+    pub fn render(
+        &self,
+        oam_frame: &mut OamIterator,
+        sprite_cache: &SpriteCache,
+        save_buffer: &SaveBuffer,
+    ) -> Option<()> {
+        match self.page {
+            TitlePage::Main => {}
+            TitlePage::Achievements => {
+                return self.render_achievements(oam_frame, sprite_cache, save_buffer);
+            }
+            TitlePage::Stats => {
+                return self.render_stats(oam_frame, sprite_cache, save_buffer);
+            }
+            TitlePage::Credits { frames } => {
+                // Half a pixel a frame, from just below the screen until it's all gone
+                let loop_px = 160 + CREDITS.lines().count() as i32 * LINE_HEIGHT;
+                let y = 160 - (frames / 2) as i32 % loop_px;
+                return draw_str(
+                    CREDITS,
+                    (120, y).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Center,
+                );
+            }
+            TitlePage::SoundTest { cursor } => {
+                return self.render_sound_test(cursor, oam_frame, sprite_cache);
+            }
+            #[cfg(debug_assertions)]
+            TitlePage::Debug => {
+                return draw_str(
+                    "D E B U G\n\nA: STRESS TEST  B: BACK",
+                    (120, 64).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Center,
+                );
+            }
+        }
+
+        draw_str(
+            "D I N O",
+            (120, 40).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+
+        // Difficulty, with arrows towards the ones it can still move to
+        draw_str(
+            self.difficulty.name(),
+            (120, 62).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        if self.difficulty != Difficulty::Easy {
+            let mut object = ObjectUnmanaged::new(sprite_cache.warning.clone());
+            object.show().set_position((76, 62).into());
+            oam_frame.next()?.set(&object);
+        }
+        if self.difficulty != Difficulty::Hard {
+            let mut object = ObjectUnmanaged::new(sprite_cache.warning.clone());
+            object.set_hflip(true).show().set_position((158, 62).into());
+            oam_frame.next()?.set(&object);
+        }
+
+        draw_str(
+            "HI",
+            (120 - 4, 76).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Right,
+        );
+        draw_score_digits(
+            save_buffer.get_score(self.difficulty),
+            false,
+            (120, 76).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Left,
+        );
+        if save_buffer.get_last_score() > 0 {
+            draw_str(
+                "LAST",
+                (120 - 4, 86).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Right,
+            );
+            draw_score_digits(
+                save_buffer.get_last_score(),
+                false,
+                (120, 86).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+        }
+        draw_str(
+            match (self.players, self.tutorial, self.calm) {
+                (_, true, _) => "TUTORIAL",
+                (_, _, true) => "CALM",
+                (1, _, _) => "1 PLAYER",
+                _ => "2 PLAYERS",
+            },
+            (120, 98).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+
+        draw_str_blinking(
+            "PRESS START",
+            (120, 114).into(),
+            60,
+            40,
+            self.frame_count,
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        if self.can_continue {
+            draw_str(
+                "L: CONTINUE",
+                (120, 128).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Center,
+            );
+        }
+        draw_str(
+            "R: OPTIONS",
+            (120, 140).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        Some(())
+    }
+
+    fn render_sound_test(
+        &self,
+        cursor: usize,
+        oam_frame: &mut OamIterator,
+        sprite_cache: &SpriteCache,
+    ) -> Option<()> {
+        draw_str(
+            "S O U N D  T E S T",
+            (120, 32).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        for (index, sound) in SoundEffectKind::ALL.iter().enumerate() {
+            let y = 52 + index as i32 * 10;
+            if index == cursor {
+                let coin_index =
+                    frame_ranger(self.frame_count, 0, sprite_cache.coin.len() as u32 - 1, 6);
+                let mut object = ObjectUnmanaged::new(sprite_cache.coin[coin_index].clone());
+                object.show().set_position((72, y).into());
+                oam_frame.next()?.set(&object);
+            }
+            draw_str(
+                sound.name(),
+                (84, y).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+        }
+        draw_str(
+            "A: PLAY  B: BACK",
+            (120, 148).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        Some(())
+    }
+
+    fn render_stats(
+        &self,
+        oam_frame: &mut OamIterator,
+        sprite_cache: &SpriteCache,
+        save_buffer: &SaveBuffer,
+    ) -> Option<()> {
+        draw_str(
+            "S T A T S",
+            (120, 36).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        for (index, (label, value)) in [
+            ("GAMES", save_buffer.get_games_played()),
+            ("AVG", save_buffer.get_average_score()),
+            ("LAST", save_buffer.get_last_score()),
+            ("FARTHEST", save_buffer.get_best_distance()),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let y = 60 + index as i32 * 12;
+            draw_str(
+                label,
+                (120 - 4, y).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Right,
+            );
+            draw_number(
+                value,
+                1,
+                false,
+                (120, y).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+        }
+        Some(())
+    }
+
+    // Earned ones are marked with a coin and the rest with a question mark
+    fn render_achievements(
+        &self,
+        oam_frame: &mut OamIterator,
+        sprite_cache: &SpriteCache,
+        save_buffer: &SaveBuffer,
+    ) -> Option<()> {
+        draw_str(
+            "A C H I E V E M E N T S",
+            (120, 36).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        for (index, achievement) in Achievement::ALL.iter().enumerate() {
+            let y = 56 + index as i32 * 12;
+            if save_buffer.is_achieved(*achievement) {
+                let mut object = ObjectUnmanaged::new(sprite_cache.coin[0].clone());
+                object.show().set_position((52, y).into());
+                oam_frame.next()?.set(&object);
+                draw_str(
+                    achievement.name(),
+                    (64, y).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Left,
+                );
+            } else {
+                draw_str(
+                    "?",
+                    (52, y).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Left,
+                );
+                draw_str(
+                    achievement.name(),
+                    (64, y).into(),
+                    oam_frame,
+                    sprite_cache,
+                    TextAlign::Left,
+                );
+            }
+        }
+        Some(())
+    }
+}
+
+// Both players' scores at the end of a two player session
+pub struct Results {
+    input: ButtonController,
+    scores: [u32; 2],
+    frame_count: u32,
+}
+
+impl Results {
+    pub fn new(scores: [u32; 2]) -> Self {
+        Self {
+            input: ButtonController::new(),
+            scores,
+            frame_count: 0,
+        }
+    }
+
+    // True once the results have been dismissed
+    pub fn frame(&mut self) -> bool {
+        self.input.update();
+        self.frame_count += 1;
+        self.input.is_just_pressed(Button::A) || self.input.is_just_pressed(Button::START)
+    }
+
+    pub fn render(&self, oam_frame: &mut OamIterator, sprite_cache: &SpriteCache) -> Option<()> {
+        draw_str(
+            "R E S U L T S",
+            (120, 40).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        for (index, (name, score)) in ["PLAYER 1", "PLAYER 2"].iter().zip(self.scores).enumerate() {
+            let y = 60 + index as i32 * 12;
+            draw_str(
+                name,
+                (60, y).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Left,
+            );
+            draw_number(
+                score,
+                1,
+                true,
+                (180, y).into(),
+                oam_frame,
+                sprite_cache,
+                TextAlign::Right,
+            );
+        }
+
+        let verdict = match self.scores[0].cmp(&self.scores[1]) {
+            core::cmp::Ordering::Greater => "PLAYER 1 WINS",
+            core::cmp::Ordering::Less => "PLAYER 2 WINS",
+            core::cmp::Ordering::Equal => "DRAW",
+        };
+        draw_str(
+            verdict,
+            (120, 92).into(),
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        draw_str_blinking(
+            "PRESS A",
+            (120, 110).into(),
+            60,
+            40,
+            self.frame_count,
+            oam_frame,
+            sprite_cache,
+            TextAlign::Center,
+        );
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constant::{MAX_JUMP_DURATION_FRAMES, MAX_JUMP_HEIGHT_PX};
+
+    // A Normal run the way the game sets one up, with a fixed seed and nothing from a save
+    fn settings() -> Settings {
+        Settings {
+            init_scroll_velocity: Difficulty::Normal.init_scroll_velocity(),
+            jump_height_px: MAX_JUMP_HEIGHT_PX,
+            jump_duration_frames: MAX_JUMP_DURATION_FRAMES,
+            fall_duration_frames: MAX_JUMP_DURATION_FRAMES,
+            min_jump_cut_multiplier: num!(0.4),
+            jump_cooldown_frames: 0,
+            max_enemies_displayed: 3,
+            show_time: true,
+            reduced_flashing: false,
+            fast_enemy_warning_frames: 30,
+            extra_lives: 0,
+            extra_life_interval: 500,
+            max_combo: 5,
+            enable_birds: true,
+            enable_cactus: true,
+            bird_bob: false,
+            weather: true,
+            players: 1,
+            rumble: false,
+            confirm_restart: true,
+            restart_hold_frames: 45,
+            resume_countdown: true,
+            tutorial: false,
+            is_ranked: true,
+            extra_spawn_gap_px: 0,
+            collision_grace_px: 0,
+            ground_y: GROUND_Y,
+            hud: HudLayout::default(),
+            day_frames: 60 * 40,
+            night_frames: 60 * 20,
+            night_transition_frames: 60 * 2,
+            spawn_interval_frames: 60,
+            coin_spawn_interval_frames: 60 * 8,
+            animation_interval_frames: 10,
+            spawn_delay_factor: Difficulty::Normal.spawn_delay_factor(),
+            scroll_velocity_increase_per_level: Difficulty::Normal
+                .scroll_velocity_increase_per_level(),
+            frames_to_level_up: 60 * 30,
+            max_speed_level: u16::MAX,
+            frames_per_point: 6,
+            speed_weighted_score: false,
+            hi_score: 0,
+            last_score: 0,
+            rng_seed: [1, 2, 3, 4],
+            achievements: 0,
+            coin_balance: 0,
+        }
+    }
+
+    fn boxed(x: u16, y: u16, width: u16, height: u16) -> Rect<u16> {
+        Rect::new((x, y).into(), (width, height).into())
+    }
+
+    fn at(x: i32, y: i32) -> Vector2D<Number> {
+        (Number::new(x), Number::new(y)).into()
+    }
+
+    #[test_case]
+    fn seven_digits_with_separators(_gba: &mut agb::Gba) {
+        // 1,234,567: a comma after the 1 and the 4, each 4px wide
+        let digits: [i32; 7] = core::array::from_fn(|index| digit_x(6 - index as u32, 7, true));
+        assert_eq!(digits, [0, 11, 18, 25, 36, 43, 50]);
+        assert_eq!(digit_x(6, 7, true) + GLYPH_WIDTH, 7);
+        assert_eq!(digit_x(3, 7, true) + GLYPH_WIDTH, 32);
+        // The whole width, as used for alignment
+        assert_eq!(digit_x(0, 7, true) + GLYPH_WIDTH, 57);
+        // Without separators the digits sit a glyph apart
+        let digits: [i32; 7] = core::array::from_fn(|index| digit_x(6 - index as u32, 7, false));
+        assert_eq!(digits, [0, 7, 14, 21, 28, 35, 42]);
+    }
+
+    #[test_case]
+    fn overlapping_boxes_collide(_gba: &mut agb::Gba) {
+        let rect = boxed(2, 4, 10, 10);
+        assert!(check_collision(rect, rect, at(20, 50), at(25, 55)));
+        assert!(check_collision(rect, rect, at(25, 55), at(20, 50)));
+    }
+
+    #[test_case]
+    fn edge_adjacent_boxes_do_not_collide(_gba: &mut agb::Gba) {
+        let rect = boxed(2, 4, 10, 10);
+        // Right edge against left edge, then bottom edge against top edge
+        assert!(!check_collision(rect, rect, at(20, 50), at(30, 50)));
+        assert!(!check_collision(rect, rect, at(20, 50), at(20, 60)));
+        // One pixel further in overlaps
+        assert!(check_collision(rect, rect, at(20, 50), at(29, 50)));
+    }
+
+    #[test_case]
+    fn separated_boxes_do_not_collide(_gba: &mut agb::Gba) {
+        let rect = boxed(2, 4, 10, 10);
+        assert!(!check_collision(rect, rect, at(20, 50), at(60, 50)));
+        assert!(!check_collision(rect, rect, at(20, 50), at(22, 90)));
+        // Just outside the dino's 32px sprite, and a sprite leaving past the left edge
+        assert!(!check_collision(rect, rect, at(20, 50), at(52, 50)));
+        assert!(!check_collision(rect, rect, at(20, 50), at(-20, 50)));
+    }
+
+    #[test_case]
+    fn calm_run_stays_gentle(_gba: &mut agb::Gba) {
+        // Steps like `simulate_run`, but kept alive for the whole ten minutes, which the
+        // simulated jumps alone might not manage
+        let mut game = headless_game(settings().calm());
+        for _ in 0..60 * 60 * 10 {
+            game.invulnerable_frames = 2;
+            let input = FrameInput {
+                jump: game.enemy_to_jump().is_some(),
+                jump_held: true,
+                ..FrameInput::default()
+            };
+            assert_eq!(
+                game.advance_frames(1, [input], &mut ()),
+                GameState::Continue
+            );
+            assert!(game.speed_level <= CALM_MAX_SPEED_LEVEL);
+            assert!(game.enemies.len() <= CALM_MAX_ENEMIES);
+            assert!(game
+                .enemies
+                .iter()
+                .all(|enemy| enemy.kind != EnemyKind::Bird));
+        }
+        assert_eq!(game.speed_level, CALM_MAX_SPEED_LEVEL);
+        assert_eq!(game.achievements, 0);
+        // Nor does its score become one for the next calm run to beat
+        game.reset();
+        assert_eq!(game.settings.hi_score, 0);
+    }
+
+    #[test_case]
+    fn crowded_spawns_keep_their_gap(_gba: &mut agb::Gba) {
+        // Every spawn due as soon as allowed, with no fast birds closing the gaps afterwards
+        // and no level ups widening the gap wanted
+        let mut game = headless_game(Settings {
+            spawn_delay_factor: Number::new(0),
+            max_enemies_displayed: 8,
+            enable_birds: false,
+            frames_to_level_up: u32::MAX,
+            ..settings()
+        });
+        let mut most_enemies = 0;
+        for _ in 0..60 * 60 {
+            step_invulnerable(&mut game);
+            for (enemy, next) in game.enemies.iter().zip(game.enemies.iter().skip(1)) {
+                let gap = next.position.x.floor() - enemy.position.x.floor();
+                assert!(gap >= game.min_spawn_gap_px(enemy));
+            }
+            most_enemies = most_enemies.max(game.enemies.len());
+        }
+        assert!(most_enemies >= 2);
+    }
+
+    #[test_case]
+    fn fast_forward_to_a_known_score(_gba: &mut agb::Gba) {
+        let idle = || core::iter::repeat(FrameInput::default());
+        let settings = settings();
+        let mut game = headless_game(settings);
+        // Nothing can reach the dino this early, so the score is the time alone
+        assert_eq!(
+            game.advance_frames(60, idle(), &mut ()),
+            GameState::Continue
+        );
+        assert_eq!(game.frame_count, 60);
+        assert_eq!(game.current_score(), 10);
+        assert_eq!(
+            game.background_position.x.floor(),
+            (settings.init_scroll_velocity * 60).floor()
+        );
+
+        // Standing still, the run ends at the first hazard, on the same frame and with the
+        // same score however the frames are fed in
+        let state = game.advance_frames(60 * 60, idle(), &mut ());
+        assert!(matches!(state, GameState::Over(_)));
+        let mut stepped = headless_game(settings);
+        for _ in 0..60 * 61 {
+            stepped.advance_frames(1, idle(), &mut ());
         }
+        assert_eq!(stepped.state, state);
+        assert_eq!(stepped.frame_count, game.frame_count);
     }
 
-    fn current_score(&self) -> u32 {
-        if self.frame_count < 6000000 {
-            self.frame_count / 6
-        } else {
-            999999
-        }
+    #[test_case]
+    fn reset_matches_a_fresh_game(_gba: &mut agb::Gba) {
+        let mut game = headless_game(settings());
+        let state =
+            game.advance_frames(60 * 60, core::iter::repeat(FrameInput::default()), &mut ());
+        assert!(matches!(state, GameState::Over(_)));
+
+        game.reset();
+        let mut fresh = headless_game(game.settings);
+        assert_eq!(game.state, GameState::Continue);
+        assert_eq!(game.lives_left, fresh.lives_left);
+        assert!(game.enemies.is_empty() && game.particles.is_empty());
+        let reset = game.snapshot(Difficulty::Normal);
+        let fresh = fresh.snapshot(Difficulty::Normal);
+        assert_eq!(reset.as_array(), fresh.as_array());
     }
 
-    pub fn frame(
-        &mut self,
-        sprite_cache: &SpriteCache,
-        vram: &mut VRamManager,
-        background: &mut InfiniteScrolledMap<'_>,
-        mixer: &mut Mixer<'_>,
-    ) -> GameState {
-        self.input.update();
+    // An idle step that can't end in a hit, for letting enemies come and go
+    fn step_invulnerable(game: &mut Game) {
+        game.invulnerable_frames = 2;
+        game.advance_frames(1, [FrameInput::default()], &mut ());
+    }
 
-        if self.input.is_just_pressed(Button::START) {
-            match self.state {
-                GameState::Continue => {
-                    self.state = GameState::Pause;
-                    return self.state;
-                }
-                GameState::Pause => {
-                    self.state = GameState::Continue;
-                    return self.state;
-                }
-                _ => {}
-            };
+    // Jumps from the ground with the button held throughout and returns how high it went
+    fn full_jump_height(game: &mut Game) -> i32 {
+        let held = FrameInput {
+            jump: true,
+            jump_held: true,
+            ..FrameInput::default()
+        };
+        game.advance_frames(1, [held], &mut ());
+        assert!(game.player.is_jumping);
+        let mut top = game.dino_grounded_y();
+        while game.player.is_jumping {
+            game.invulnerable_frames = 2;
+            game.advance_frames(1, [held], &mut ());
+            top = top.min(game.player.position.y.floor());
         }
+        game.dino_grounded_y() - top
+    }
 
-        match self.state {
-            GameState::Over(_) => {
-                if self.input.is_just_pressed(Button::A)
-                    || self.input.is_just_pressed(Button::START)
-                {
-                    // reset game
-                    self.state = GameState::Restart;
-                }
-                return self.state;
-            }
-            GameState::Pause => {
-                return self.state;
-            }
-            _ => {}
-        }
+    #[test_case]
+    fn new_jump_params_set_the_apex(_gba: &mut agb::Gba) {
+        let mut game = headless_game(settings());
+        game.set_jump_params(20, 12, 12);
+        assert!((full_jump_height(&mut game) - 20).abs() <= 1);
+        // A zero duration is taken as a single frame rather than dividing by zero
+        game.set_jump_params(20, 0, 0);
+        assert!((full_jump_height(&mut game) - 20).abs() <= 1);
+    }
 
-        self.frame_count += 1;
-        self.frames_current_level += 1;
-        self.frames_since_last_spawn += 1;
+    #[test_case]
+    fn two_milestones_grant_two_lives(_gba: &mut agb::Gba) {
+        let mut game = headless_game(Settings {
+            extra_life_interval: 100,
+            ..settings()
+        });
+        let lives = game.lives_left;
+        let mut events = Vec::new();
+        game.bonus_score = 100;
+        game.advance_frames(1, [FrameInput::default()], &mut events);
+        game.bonus_score = 200;
+        game.advance_frames(10, core::iter::repeat(FrameInput::default()), &mut events);
+        assert_eq!(game.lives_left, lives + 2);
+        let extra_lives = events
+            .iter()
+            .filter(|event| matches!(event, GameEvent::ExtraLife))
+            .count();
+        assert_eq!(extra_lives, 2);
+    }
 
-        // Update random spawn info
-        if self.spawn_queue.is_empty() {
-            let rnd = agb::rng::gen() as u32;
-            for i in 0..4 {
-                let spawn_info = SpawnInfo::from(((rnd >> (i * 8)) & 0xFF) as u8);
-                self.spawn_queue.push_back(spawn_info);
-            }
+    #[test_case]
+    fn disabled_birds_never_spawn(_gba: &mut agb::Gba) {
+        // Levelling up every second reaches the speeds where birds usually turn up
+        let mut game = headless_game(Settings {
+            enable_birds: false,
+            frames_to_level_up: 60,
+            ..settings()
+        });
+        let mut most_enemies = 0;
+        for _ in 0..60 * 60 {
+            step_invulnerable(&mut game);
+            assert!(game
+                .enemies
+                .iter()
+                .all(|enemy| enemy.kind != EnemyKind::Bird));
+            most_enemies = most_enemies.max(game.enemies.len());
         }
+        assert!(most_enemies > 0);
+    }
 
-        // Process level up
-        if self.frames_current_level >= self.settings.frames_to_level_up {
-            print_info(
-                &mut self.mgba,
-                format_args!("level up: {}", self.speed_level + 1),
-            );
-            play_sound(mixer, SoundEffectKind::Up);
+    #[test_case]
+    fn full_jump_reaches_its_height(_gba: &mut agb::Gba) {
+        for (height, duration) in [(MAX_JUMP_HEIGHT_PX, MAX_JUMP_DURATION_FRAMES), (37, 19)] {
+            let mut game = headless_game(Settings {
+                jump_height_px: height,
+                jump_duration_frames: duration,
+                fall_duration_frames: duration,
+                ..settings()
+            });
+            let peak = full_jump_height(&mut game);
+            assert!((peak - height as i32).abs() <= 1);
+        }
+    }
 
-            self.scroll_velocity += self.settings.scroll_velocity_increase_per_level;
-            self.speed_level += 1;
-            self.frames_current_level = 0;
+    #[test_case]
+    fn each_bird_band_asks_for_its_own_move(_gba: &mut agb::Gba) {
+        let grounded = DINO_GROUNDED_Y as i32;
+        let hits = |dino_rect: Rect<u16>, dino_y: i32, band: BirdBand| {
+            check_collision(
+                dino_rect,
+                BIRD_COLLISION_RECT,
+                at(DINO_X, dino_y),
+                at(DINO_X, band.y()),
+            )
+        };
+        let top = grounded - MAX_JUMP_HEIGHT_PX as i32;
+        let rising = grounded - 20;
+
+        // Standing still only gets away with a high bird
+        assert!(hits(DINO_COLLISION_RECT, grounded, BirdBand::Ground));
+        assert!(hits(DINO_COLLISION_RECT, grounded, BirdBand::Mid));
+        assert!(!hits(DINO_COLLISION_RECT, grounded, BirdBand::High));
+        // Ducking gets under a mid bird too, but not over a ground one
+        assert!(hits(DINO_DUCK_COLLISION_RECT, grounded, BirdBand::Ground));
+        assert!(!hits(DINO_DUCK_COLLISION_RECT, grounded, BirdBand::Mid));
+        assert!(!hits(DINO_DUCK_COLLISION_RECT, grounded, BirdBand::High));
+        // A full jump clears them all at its top, but rises into a high bird on the way
+        for band in [BirdBand::Ground, BirdBand::Mid, BirdBand::High] {
+            assert!(!hits(DINO_COLLISION_RECT, top, band));
         }
+        assert!(!hits(DINO_COLLISION_RECT, rising, BirdBand::Ground));
+        assert!(hits(DINO_COLLISION_RECT, rising, BirdBand::High));
+    }
 
-        // Calc player position
-        if self.player.is_jumping {
-            self.player.position.y += self.player.vertical_speed;
-            let player_y_px = self.player.position.y.floor();
-            if player_y_px >= DINO_GROUNDED_Y as i32 {
-                self.player.position.y = Num::new(DINO_GROUNDED_Y as i32);
-                self.player.is_jumping = false;
-            }
-            self.player.vertical_speed += self.gravity_px_per_square_frame;
-        } else if self.input.is_just_pressed(Button::A) {
-            play_sound(mixer, SoundEffectKind::Jump);
+    #[test_case]
+    fn jump_waits_out_its_cooldown(_gba: &mut agb::Gba) {
+        let cooldown = 10;
+        let mut game = headless_game(Settings {
+            jump_cooldown_frames: cooldown,
+            ..settings()
+        });
+        let press = FrameInput {
+            jump: true,
+            ..FrameInput::default()
+        };
+        full_jump_height(&mut game);
 
-            self.player.vertical_speed =
-                -self.gravity_px_per_square_frame * (self.settings.jump_duration_frames as i32);
-            self.player.is_jumping = true;
+        // Straight after landing
+        game.advance_frames(1, [press], &mut ());
+        assert!(!game.player.is_jumping);
+        game.advance_frames(cooldown, core::iter::repeat(FrameInput::default()), &mut ());
+        game.advance_frames(1, [press], &mut ());
+        assert!(game.player.is_jumping);
+    }
+
+    #[test_case]
+    fn easy_spacing_is_looser_than_hard(_gba: &mut agb::Gba) {
+        let for_difficulty = |difficulty: Difficulty| {
+            headless_game(Settings {
+                spawn_delay_factor: difficulty.spawn_delay_factor(),
+                ..settings()
+            })
+        };
+        let easy = for_difficulty(Difficulty::Easy);
+        let hard = for_difficulty(Difficulty::Hard);
+        for roll in 0..=u8::MAX {
+            let spawn_info = SpawnInfo::from(roll);
+            assert!(easy.spawn_delay(&spawn_info) > hard.spawn_delay(&spawn_info));
+            assert!(hard.spawn_delay(&spawn_info) >= MIN_SPAWN_DELAY_FRAMES);
         }
+    }
 
-        // Spawn enemy
-        if self.frames_since_last_spawn > self.spawn_queue.front().unwrap().delay() {
-            let spawn_info = self.spawn_queue.pop_front().unwrap();
-            print_info(
-                &mut self.mgba,
-                format_args!(
-                    "[T={}, dt={}] spawn: {} {:?} {}",
-                    self.frame_count,
-                    self.frames_since_last_spawn,
-                    spawn_info.delay(),
-                    spawn_info.enemy_kind(),
-                    spawn_info.enemy_arg_2bit()
-                ),
-            );
-            self.frames_since_last_spawn = 0;
+    #[test_case]
+    fn recorded_run_verifies_only_its_own_score(_gba: &mut agb::Gba) {
+        // A couple of early jumps, then standing still until the first hazard
+        let inputs: Vec<FrameInput> = (0..60 * 60)
+            .map(|frame| FrameInput {
+                jump: frame % 60 == 0 && frame < 180,
+                jump_held: frame < 180,
+                ..FrameInput::default()
+            })
+            .collect();
+        let mut game = headless_game(settings());
+        let GameState::Over(score) =
+            game.advance_frames(inputs.len() as u32, inputs.iter().copied(), &mut ())
+        else {
+            panic!("the run should be over");
+        };
 
-            if self.enemies.len() < self.enemies.capacity() {
-                let enemy = match spawn_info.enemy_kind() {
-                    EnemyKind::Bird => {
-                        let spawn_y = (spawn_info.enemy_arg_2bit() as i32 + 6) * 8;
-                        Enemy {
-                            kind: EnemyKind::Bird,
-                            position: (8 * 30, spawn_y).into(),
-                        }
-                    }
-                    EnemyKind::Cactus => {
-                        // let n_cactuses = spawn_info.enemy_arg() & 0b1 + 1;
-                        Enemy {
-                            kind: EnemyKind::Cactus,
-                            position: (8 * 30, CACTUS_Y as i32).into(),
-                        }
-                    }
-                };
-                self.enemies.push_back(enemy);
+        assert!(verify_run(settings(), &inputs, score));
+        assert!(!verify_run(settings(), &inputs, score + 1));
+        // Cut off before the hit, the run never ends
+        let frames = game.frame_count as usize;
+        assert!(!verify_run(settings(), &inputs[..frames / 2], score));
+    }
+
+    #[test_case]
+    fn spawn_burst_stays_within_capacity(_gba: &mut agb::Gba) {
+        // Every spawn due as soon as allowed, at full speed, for as long as the dino lasts,
+        // with the cap raised mid-run like the stress run does
+        let mut game = headless_game(Settings {
+            spawn_delay_factor: Number::new(0),
+            max_enemies_displayed: 3,
+            frames_to_level_up: 60,
+            ..settings()
+        });
+        let spawn_queue = game.spawn_queue.capacity();
+        let collectibles = game.collectibles.capacity();
+        for max_enemies in [3, 8] {
+            game.set_max_enemies_displayed(max_enemies);
+            let enemies = game.enemies.capacity();
+            assert!(enemies >= max_enemies);
+            for _ in 0..60 * 30 {
+                step_invulnerable(&mut game);
+                assert!(game.enemies.len() <= max_enemies);
+                assert_eq!(game.enemies.capacity(), enemies);
+                assert_eq!(game.spawn_queue.capacity(), spawn_queue);
+                assert_eq!(game.collectibles.capacity(), collectibles);
             }
         }
+    }
 
-        // Calc enemies' position and collision detection
-        let mut player_collision_rect = sprite_cache.dino.get(0).unwrap().rect;
-        player_collision_rect.position += (
-            self.player.position.x.floor() as u16,
-            self.player.position.y.floor() as u16,
-        )
-            .into();
-        let mut total_enemies_out: usize = 0;
-        let mut is_collided: bool = false;
-        for enemy in self.enemies.iter_mut() {
-            if enemy.position.x.floor() < -32 {
-                total_enemies_out += 1;
-            } else {
-                enemy.position.x -= self.scroll_velocity;
+    #[test_case]
+    fn score_follows_its_rate_up_to_the_cap(_gba: &mut agb::Gba) {
+        let mut game = headless_game(Settings {
+            frames_per_point: 4,
+            ..settings()
+        });
+        game.advance_frames(60, core::iter::repeat(FrameInput::default()), &mut ());
+        assert_eq!(game.current_score(), 15);
 
-                // Collision detection
-                if self.player.position.x <= enemy.position.x + 32
-                    && enemy.position.x <= self.player.position.x + 32
-                {
-                    let mut enemy_collision_rect = match enemy.kind {
-                        EnemyKind::Bird => sprite_cache.bird.get(0).unwrap().rect,
-                        EnemyKind::Cactus => sprite_cache.cactus.rect,
-                    };
-                    enemy_collision_rect.position += (
-                        enemy.position.x.floor() as u16,
-                        enemy.position.y.floor() as u16,
-                    )
-                        .into();
+        game.frame_count = 4 * MAX_SCORE;
+        assert_eq!(game.current_score(), MAX_SCORE);
+        game.frame_count = u32::MAX;
+        game.bonus_score = u32::MAX;
+        assert_eq!(game.current_score(), MAX_SCORE);
+    }
 
-                    if enemy_collision_rect.touches(player_collision_rect) {
-                        print_info(&mut self.mgba, format_args!("collide: {:?}", enemy.kind));
-                        is_collided = true;
-                    }
-                }
-            };
-        }
-        if is_collided {
-            play_sound(mixer, SoundEffectKind::Over);
-            self.state = GameState::Over(self.current_score());
-        }
+    // The position written to an OAM slot, straight from object attribute memory
+    fn oam_slot_position(slot: usize) -> Vector2D<i32> {
+        let attributes = 0x0700_0000 as *const u16;
+        // SAFETY: OAM is always mapped, and 128 slots of four halfwords long
+        let (attr0, attr1) = unsafe {
+            (
+                attributes.add(slot * 4).read_volatile(),
+                attributes.add(slot * 4 + 1).read_volatile(),
+            )
+        };
+        ((attr1 & 0x1FF) as i32, (attr0 & 0xFF) as i32).into()
+    }
 
-        // Remove first n enemies which are out of screen
-        self.enemies.drain(..total_enemies_out);
+    #[test_case]
+    fn dino_and_score_go_in_first(gba: &mut agb::Gba) {
+        let (mut oam, mut sprite_loader) = gba.display.object.get_unmanaged();
+        let sprite_cache = SpriteCache::new(&mut sprite_loader);
+        let mut game = headless_game(settings());
+        game.render(&mut oam.iter(), &sprite_cache);
 
-        self.background_position.x += self.scroll_velocity;
-        background.set_pos(vram, self.background_position.floor());
-        self.state
+        // With nothing on screen yet the score's first digit follows the dino straight away
+        assert_eq!(oam_slot_position(0), game.player.position.floor());
+        assert_eq!(oam_slot_position(1).y, game.settings.hud.score.y);
     }
 
-    pub fn render(
-        &mut self,
-        oam_frame: &mut OamIterator,
-        sprite_cache: &SpriteCache,
-    ) -> Option<()> {
-        let sprite_index: usize = frame_ranger(
-            self.frame_count,
-            0,
-            1,
-            self.settings.animation_interval_frames as u32,
+    #[test_case]
+    fn simulated_run_outlasts_standing_still(_gba: &mut agb::Gba) {
+        // Ground hazards only, as the simple player jumps into high birds
+        let settings = Settings {
+            enable_birds: false,
+            ..settings()
+        };
+        let max_frames = 60 * 60 * 5;
+        let mut idle = headless_game(settings);
+        idle.advance_frames(
+            max_frames,
+            core::iter::repeat(FrameInput::default()),
+            &mut (),
         );
 
-        // Draw player
-        let sprite = match self.state {
-            GameState::Over(_) => sprite_cache.dino.get(2).unwrap().sprite.clone(),
-            _ => {
-                if self.player.is_jumping {
-                    sprite_cache.dino.get(1).unwrap().sprite.clone()
-                } else {
-                    sprite_cache.dino.get(sprite_index).unwrap().sprite.clone()
+        let result = simulate_run(settings, max_frames);
+        assert!(result.frames > idle.frame_count);
+        assert!(result.frames <= max_frames);
+        assert!(result.is_over || result.frames == max_frames);
+        assert!(result.score >= result.frames / settings.frames_per_point);
+        assert!(result.distance_m > 0);
+    }
+
+    #[test_case]
+    fn text_is_as_wide_as_its_glyph_advances(gba: &mut agb::Gba) {
+        let (mut oam, mut sprite_loader) = gba.display.object.get_unmanaged();
+        let sprite_cache = SpriteCache::new(&mut sprite_loader);
+        let text = "JIT LOOK";
+        let width: i32 = text.chars().map(glyph_advance).sum();
+        // J, I, T, the space and L are all narrower than a full glyph
+        assert_eq!(width, 3 * 6 + 5 + 6 + 3 * GLYPH_WIDTH);
+
+        for (align, x, left) in [
+            (TextAlign::Left, 10, 10),
+            (TextAlign::Right, 120, 120 - width),
+        ] {
+            draw_str(text, (x, 40).into(), &mut oam.iter(), &sprite_cache, align);
+            // The space takes no slot, only room
+            let mut expected_x = left;
+            let mut slot = 0;
+            for char in text.chars() {
+                if char != ' ' {
+                    assert_eq!(oam_slot_position(slot), (expected_x, 40).into());
+                    slot += 1;
                 }
+                expected_x += glyph_advance(char);
             }
-        };
-        let mut player_object = ObjectUnmanaged::new(sprite);
-        player_object
-            .show()
-            .set_position(self.player.position.floor());
-        oam_frame.next()?.set(&player_object);
+        }
+    }
 
-        // Draw enemy
-        for enemy in self.enemies.iter() {
-            let sprite = match enemy.kind {
-                EnemyKind::Bird => sprite_cache.bird.get(sprite_index).unwrap().sprite.clone(),
-                EnemyKind::Cactus => sprite_cache.cactus.sprite.clone(),
-            };
-            let mut object = ObjectUnmanaged::new(sprite);
-            object.show().set_position(enemy.position.floor());
-            oam_frame.next()?.set(&object);
+    #[test_case]
+    fn snapshot_restores_the_run(_gba: &mut agb::Gba) {
+        let mut game = headless_game(settings());
+        for _ in 0..60 * 10 {
+            step_invulnerable(&mut game);
         }
+        assert!(!game.enemies.is_empty());
 
-        // Draw score
-        let score = self.current_score();
-        let score_value_right = 236;
-        let score_y = (BG_TILES_OFFSET_Y * 8 - 9) as i32;
-        draw_score_digits(
-            score,
-            (score_value_right, score_y).into(),
-            oam_frame,
-            sprite_cache,
-            TextAlign::Right,
+        let snapshot = game.snapshot(Difficulty::Normal);
+        let (difficulty, fields) = snapshot.read().unwrap();
+        assert_eq!(difficulty, Difficulty::Normal);
+        let mut restored = Game::from_snapshot(settings(), fields).unwrap();
+        assert_eq!(restored.state, GameState::Pause);
+        assert_eq!(restored.current_score(), game.current_score());
+        assert_eq!(restored.player.position, game.player.position);
+        assert_eq!(restored.background_position, game.background_position);
+        assert_eq!(restored.enemies.len(), game.enemies.len());
+        for (restored, enemy) in restored.enemies.iter().zip(game.enemies.iter()) {
+            assert_eq!(restored.kind, enemy.kind);
+            assert_eq!(restored.position, enemy.position);
+        }
+        // Everything else stored, the rng included, comes back as it was too
+        let resnapshot = restored.snapshot(Difficulty::Normal);
+        assert_eq!(
+            resnapshot.as_array(),
+            game.snapshot(Difficulty::Normal).as_array()
         );
-        draw_str(
-            "SCORE",
-            (score_value_right - 7 * 6 - 2, score_y + 1).into(),
-            oam_frame,
-            sprite_cache,
-            TextAlign::Right,
+    }
+
+    fn cactus_at(game: &Game, x: i32) -> Enemy {
+        let y = CACTUS_Y as i32 + game.settings.ground_offset();
+        Enemy {
+            kind: EnemyKind::Cactus(CactusSize::Normal),
+            position: at(x, y),
+            triggered: false,
+            is_fast: false,
+            grazed: false,
+            sway_phase: 0,
+            spawn_frame: game.frame_count,
+            spawn_y: Number::new(y),
+        }
+    }
+
+    #[test_case]
+    fn hit_after_three_dodges_resets_the_combo(_gba: &mut agb::Gba) {
+        let mut game = headless_game(Settings {
+            extra_lives: 1,
+            ..settings()
+        });
+        // Already past the left edge, so all three count as dodged on the next step
+        for _ in 0..3 {
+            let cactus = cactus_at(&game, -100);
+            game.enemies.push_back(cactus);
+        }
+        game.advance_frames(1, [FrameInput::default()], &mut ());
+        assert_eq!(game.enemies_dodged, 3);
+        assert_eq!(game.combo, 4);
+
+        let cactus = cactus_at(&game, DINO_X);
+        game.enemies.push_back(cactus);
+        let mut events = Vec::new();
+        game.advance_frames(1, [FrameInput::default()], &mut events);
+        assert!(events.contains(&GameEvent::LifeLost));
+        assert_eq!(game.combo, 1);
+    }
+
+    #[test_case]
+    fn catch_up_steps_match_single_frames(_gba: &mut agb::Gba) {
+        // Hopping every second, so the dino's arc is part of what has to match
+        let input = |frame: u32| FrameInput {
+            jump: frame % 60 == 0,
+            jump_held: frame % 60 < 20,
+            ..FrameInput::default()
+        };
+        let frames = 60 * 4;
+        let mut caught_up = headless_game(settings());
+        let mut stepped = headless_game(settings());
+        for frame in (0..frames).step_by(4) {
+            caught_up.advance_frames(4, (frame..frame + 4).map(input), &mut ());
+        }
+        for frame in 0..frames {
+            stepped.advance_frames(1, [input(frame)], &mut ());
+        }
+        assert_eq!(caught_up.state, stepped.state);
+        assert_eq!(
+            caught_up.snapshot(Difficulty::Normal).as_array(),
+            stepped.snapshot(Difficulty::Normal).as_array()
         );
+    }
 
-        // Draw hi score
-        let hi_score_y = (BG_TILES_OFFSET_Y * 8 - 18) as i32;
-        draw_score_digits(
-            self.settings.hi_score,
-            (score_value_right, hi_score_y).into(),
-            oam_frame,
-            sprite_cache,
-            TextAlign::Right,
+    #[test_case]
+    fn fall_speed_leaves_the_apex_alone(_gba: &mut agb::Gba) {
+        for fall_frames in [6, MAX_JUMP_DURATION_FRAMES, 30] {
+            let mut game = headless_game(Settings {
+                fall_duration_frames: fall_frames,
+                ..settings()
+            });
+            let peak = full_jump_height(&mut game);
+            assert!((peak - MAX_JUMP_HEIGHT_PX as i32).abs() <= 1);
+        }
+    }
+
+    #[test_case]
+    fn first_upcoming_spawn_comes_next(_gba: &mut agb::Gba) {
+        let idle = || core::iter::repeat(FrameInput::default());
+        let mut game = headless_game(settings());
+        // The queue is first filled on the first step
+        game.advance_frames(1, idle(), &mut ());
+        let (spawn_info, frames) = game.upcoming_spawns().next().unwrap();
+
+        game.advance_frames(frames - 1, idle(), &mut ());
+        assert!(game.enemies.is_empty());
+        game.advance_frames(1, idle(), &mut ());
+        assert_eq!(game.enemies.len(), 1);
+        assert_eq!(game.enemies[0].kind, spawn_info.enemy_kind());
+    }
+
+    #[test_case]
+    fn lowered_enemy_cap_holds_back_spawns(_gba: &mut agb::Gba) {
+        let mut game = headless_game(Settings {
+            spawn_delay_factor: Number::new(0),
+            max_enemies_displayed: 6,
+            ..settings()
+        });
+        let step = |game: &mut Game| {
+            step_invulnerable(game);
+            game.enemies.len()
+        };
+        while step(&mut game) < 3 {}
+
+        // Nothing new comes in until the ones already out have scrolled down to the new cap
+        game.set_max_enemies_displayed(1);
+        let mut enemies = game.enemies.len();
+        for _ in 0..60 * 10 {
+            let now = step(&mut game);
+            assert!(now <= enemies.max(1));
+            enemies = now;
+        }
+        assert!(enemies <= 1);
+
+        game.set_max_enemies_displayed(6);
+        let mut most_enemies = 0;
+        for _ in 0..60 * 10 {
+            most_enemies = most_enemies.max(step(&mut game));
+        }
+        assert!(most_enemies > 1);
+    }
+
+    #[test_case]
+    fn grace_lets_a_graze_through(_gba: &mut agb::Gba) {
+        // The cactus's box starts on the last column of the dino's
+        let dino = at(DINO_X, DINO_GROUNDED_Y as i32);
+        let cactus = at(DINO_X + 25, CACTUS_Y as i32);
+        let hits = |grace_px: u16| {
+            check_collision(
+                shrunk_rect(DINO_COLLISION_RECT, grace_px),
+                shrunk_rect(CACTUS_COLLISION_RECT, grace_px),
+                dino,
+                cactus,
+            )
+        };
+        assert!(hits(0));
+        assert!(!hits(2));
+
+        // However much grace, a box keeps some size
+        let tiny = shrunk_rect(boxed(4, 4, 3, 5), 10);
+        assert_eq!(tiny, boxed(4, 5, 3, 3));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test_case]
+    fn pause_and_resume_are_logged(_gba: &mut agb::Gba) {
+        // Pausing after half a second, resuming 20 steps later, then standing still until hit
+        let inputs: Vec<FrameInput> = (0..60 * 60)
+            .map(|index| FrameInput {
+                pause: index == 30 || index == 51,
+                ..FrameInput::default()
+            })
+            .collect();
+        let transitions = replay_transitions(settings(), &inputs);
+
+        // The run doesn't count paused steps, so both happen on its frame 30
+        assert_eq!(
+            transitions[..2],
+            [
+                StateTransition {
+                    frame: 30,
+                    from: GameState::Continue,
+                    to: GameState::Pause,
+                },
+                StateTransition {
+                    frame: 30,
+                    from: GameState::Pause,
+                    to: GameState::Continue,
+                },
+            ]
         );
-        draw_str(
-            "HI",
-            (score_value_right - 7 * 6 - 2, hi_score_y + 1).into(),
-            oam_frame,
-            sprite_cache,
-            TextAlign::Right,
+        assert_eq!(transitions.len(), 3);
+        assert_eq!(transitions[2].from, GameState::Continue);
+        assert!(matches!(transitions[2].to, GameState::Over(_)));
+    }
+
+    #[test_case]
+    fn bird_box_spares_its_feathers(_gba: &mut agb::Gba) {
+        let hits = |dino_rect: Rect<u16>, dino_y: i32, bird_y: i32| {
+            check_collision(
+                dino_rect,
+                BIRD_COLLISION_RECT,
+                at(DINO_X, dino_y),
+                at(DINO_X, bird_y),
+            )
+        };
+        let grounded = DINO_GROUNDED_Y as i32;
+        let mid = BirdBand::Mid.y();
+
+        // A head reaching into the trimmed bottom row of the body is spared, one a row higher
+        // isn't
+        let body_bottom =
+            mid + (BIRD_COLLISION_RECT.position.y + BIRD_COLLISION_RECT.size.y) as i32;
+        let head = DINO_COLLISION_RECT.position.y as i32;
+        assert!(!hits(DINO_COLLISION_RECT, body_bottom - head, mid));
+        assert!(hits(DINO_COLLISION_RECT, body_bottom - head - 1, mid));
+
+        // Bobbing all the way either way, a mid bird still passes over a ducking dino and a
+        // high one over a standing dino
+        for bob in [-BIRD_BOB_PX, 0, BIRD_BOB_PX] {
+            assert!(!hits(DINO_DUCK_COLLISION_RECT, grounded, mid + bob));
+            assert!(!hits(
+                DINO_COLLISION_RECT,
+                grounded,
+                BirdBand::High.y() + bob
+            ));
+        }
+    }
+
+    #[test_case]
+    fn faster_runs_score_faster_when_weighted(_gba: &mut agb::Gba) {
+        // Over before anything has been dodged, so the score is down to distance or time alone
+        let score_after_90_frames = |speed_weighted_score: bool, frames_to_level_up: u32| {
+            let mut game = headless_game(Settings {
+                speed_weighted_score,
+                frames_to_level_up,
+                max_speed_level: 5,
+                ..settings()
+            });
+            for _ in 0..90 {
+                step_invulnerable(&mut game);
+            }
+            game.current_score()
+        };
+        // Up to level 5 in under a second against staying on level 0
+        assert!(score_after_90_frames(true, 10) > score_after_90_frames(true, u32::MAX));
+        assert_eq!(
+            score_after_90_frames(false, 10),
+            score_after_90_frames(false, u32::MAX)
         );
+    }
 
-        match self.state {
-            GameState::Over(_) => {
-                draw_str(
-                    "G A M E  O V E R",
-                    (120, 60).into(),
-                    oam_frame,
-                    sprite_cache,
-                    TextAlign::Center,
-                );
-                draw_str(
-                    "PRESS A TO RESTART",
-                    (120, 75).into(),
-                    oam_frame,
-                    sprite_cache,
-                    TextAlign::Center,
-                );
+    #[test_case]
+    fn birds_bob_only_when_asked(_gba: &mut agb::Gba) {
+        for bird_bob in [false, true] {
+            let mut game = headless_game(Settings {
+                bird_bob,
+                ..settings()
+            });
+            let y = BirdBand::Mid.y();
+            game.enemies.push_back(Enemy {
+                kind: EnemyKind::Bird,
+                position: at(200, y),
+                triggered: false,
+                is_fast: false,
+                grazed: false,
+                sway_phase: 0,
+                spawn_frame: game.frame_count,
+                spawn_y: Number::new(y),
+            });
+            let (mut lowest, mut highest) = (y, y);
+            // Half a swing, out to one side and back level
+            for _ in 0..BIRD_BOB_PERIOD_FRAMES / 2 {
+                step_invulnerable(&mut game);
+                let bird_y = game.enemies[0].position.y.floor();
+                lowest = lowest.max(bird_y);
+                highest = highest.min(bird_y);
             }
-            GameState::Pause => {
-                draw_str(
-                    "P A U S E D",
-                    (120, 60).into(),
-                    oam_frame,
-                    sprite_cache,
-                    TextAlign::Center,
-                );
-                draw_str(
-                    "PRESS START TO RESUME",
-                    (120, 75).into(),
-                    oam_frame,
-                    sprite_cache,
-                    TextAlign::Center,
-                );
+            if bird_bob {
+                assert!(highest < y || lowest > y);
+                assert!(y - highest <= BIRD_BOB_PX && lowest - y <= BIRD_BOB_PX);
+            } else {
+                assert_eq!((lowest, highest), (y, y));
             }
-            _ => {}
         }
+    }
+
+    #[test_case]
+    fn passing_through_while_invulnerable_is_no_near_miss(_gba: &mut agb::Gba) {
+        let mut game = headless_game(settings());
+        let cactus = cactus_at(&game, DINO_X + 40);
+        game.enemies.push_back(cactus);
+        let mut events = Vec::new();
+        for _ in 0..60 {
+            game.invulnerable_frames = 2;
+            game.advance_frames(1, [FrameInput::default()], &mut events);
+        }
+        assert!(!events.contains(&GameEvent::NearMiss));
+        assert_eq!(game.near_misses, 0);
+    }
 
-        Some(())
+    #[test_case]
+    fn narrow_enemies_hit_at_their_box_edges(_gba: &mut agb::Gba) {
+        // Far enough in that no box reaches past the left edge of the screen
+        let dino_x = 64;
+        let dino_y = DINO_GROUNDED_Y as i32;
+        let dino_left = dino_x + DINO_COLLISION_RECT.position.x as i32;
+        let dino_right = dino_left + DINO_COLLISION_RECT.size.x as i32;
+        for (rect, enemy_y) in [
+            (CACTUS_SMALL_COLLISION_RECT, CACTUS_Y as i32),
+            (BIRD_COLLISION_RECT, BirdBand::Ground.y()),
+        ] {
+            let hits = |x: i32| {
+                let (dino, enemy) = (at(dino_x, dino_y), at(x, enemy_y));
+                let hit = check_collision(DINO_COLLISION_RECT, rect, dino, enemy);
+                // The window is no wider than the boxes themselves
+                assert_eq!(
+                    is_in_collision_window(DINO_COLLISION_RECT, rect, dino, enemy),
+                    hit
+                );
+                hit
+            };
+            // The enemy's right edge on the dino's left edge, then its left edge on the dino's
+            // right edge
+            let right_edge_on_dino = dino_left - (rect.position.x + rect.size.x) as i32;
+            let left_edge_on_dino = dino_right - rect.position.x as i32;
+            assert!(!hits(right_edge_on_dino));
+            assert!(hits(right_edge_on_dino + 1));
+            assert!(hits(left_edge_on_dino - 1));
+            assert!(!hits(left_edge_on_dino));
+        }
+    }
+
+    #[test_case]
+    fn enemies_leave_once_their_sprite_is_off_screen(_gba: &mut agb::Gba) {
+        let tall = EnemyKind::Cactus(CactusSize::Tall);
+        let width = tall.width();
+        for (x, kept) in [(-width + 1, true), (-width, false)] {
+            let mut game = headless_game(settings());
+            game.enemies.push_back(Enemy {
+                kind: tall,
+                ..cactus_at(&game, x)
+            });
+            // Frozen in place, so the step removes it or not from where it was put
+            game.freeze_frames = 2;
+            step_invulnerable(&mut game);
+            // Anything spawned on the step comes in from the right
+            let still_there = game
+                .enemies
+                .iter()
+                .any(|enemy| enemy.position.x < Number::new(0));
+            assert_eq!(still_there, kept);
+            assert_eq!(game.enemies_dodged, u32::from(!kept));
+        }
+    }
+
+    #[test_case]
+    fn fractional_scroll_moves_by_whole_pixels(_gba: &mut agb::Gba) {
+        let mut game = headless_game(Settings {
+            init_scroll_velocity: num!(2.5),
+            ..settings()
+        });
+        let cactus = cactus_at(&game, 200);
+        game.enemies.push_back(cactus);
+        let mut ground_steps = Vec::new();
+        for _ in 0..20 {
+            let (ground_x, enemy_x) = (game.background_position.x, game.enemies[0].position.x);
+            step_invulnerable(&mut game);
+            let ground_step = game.background_position.x - ground_x;
+            assert_eq!(Number::new(ground_step.floor()), ground_step);
+            // The enemies take their extra pixel on the same frames as the ground
+            assert_eq!(ground_step, enemy_x - game.enemies[0].position.x);
+            ground_steps.push(ground_step.floor());
+        }
+        // 2.5px a frame comes out as 2 and 3 in turn, never drifting from the true distance
+        assert!(ground_steps.windows(2).all(|pair| pair[0] + pair[1] == 5));
+        assert!(ground_steps.iter().all(|&step| step == 2 || step == 3));
+        assert_eq!(ground_steps.iter().sum::<i32>(), 50);
+    }
+
+    #[test_case]
+    fn moved_ground_keeps_the_jump_height(_gba: &mut agb::Gba) {
+        for ground_y in [GROUND_Y - 24, GROUND_Y + 16] {
+            let mut game = headless_game(Settings {
+                ground_y,
+                ..settings()
+            });
+            let grounded = DINO_GROUNDED_Y as i32 + game.settings.ground_offset();
+            assert_eq!(game.player.position.y.floor(), grounded);
+            let peak = full_jump_height(&mut game);
+            assert!((peak - MAX_JUMP_HEIGHT_PX as i32).abs() <= 1);
+            // Lands back on the moved ground rather than the standard one
+            assert_eq!(game.player.position.y.floor(), grounded);
+        }
     }
 }
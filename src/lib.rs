@@ -6,6 +6,7 @@
 // to use both the `core` and the `alloc` built in crates.
 #![no_std]
 // This is required to allow writing tests
+#![cfg_attr(test, no_main)]
 #![cfg_attr(test, feature(custom_test_frameworks))]
 #![cfg_attr(test, reexport_test_harness_main = "test_main")]
 #![cfg_attr(test, test_runner(agb::test_runner::test_runner))]
@@ -14,30 +15,42 @@ extern crate alloc;
 
 use agb::{
     display::{
-        tiled::{InfiniteScrolledMap, RegularBackgroundSize, TileFormat},
+        tiled::{InfiniteScrolledMap, RegularBackgroundSize, TileFormat, TiledMap},
         Priority,
     },
     fixnum::num,
     mgba::Mgba,
     save::{Error, SaveData},
-    sound::mixer::Frequency,
 };
 use alloc::boxed::Box;
-use constant::{MAX_JUMP_DURATION_FRAMES, MAX_JUMP_HEIGHT_PX};
+use constant::{MAX_JUMP_DURATION_FRAMES, MAX_JUMP_HEIGHT_PX, STEPS_PER_VBLANK};
 use game::{
     resource::{
-        create_tile_map, BG_BLANK_TILE_IDX, BG_PALETTES, BG_TILES_DATA, BG_TILES_HEIGHT,
-        BG_TILES_OFFSET_Y,
+        background_tile, create_tile_map, BG_PALETTES, BG_TILES_DATA, GROUND_Y, MIXER_FREQUENCY,
     },
-    Game, GameState, Settings, SpriteCache,
+    Difficulty, Game, GameState, Number, Options, OptionsEvent, Results, Shop, ShopEvent, Skin,
+    SpriteCache, Title, TitleEvent,
+};
+use save::{
+    GameSnapshot, Preference, SaveBuffer, Unlock, SAVE_BACKUP_OFFSET, SAVE_SIZE, SNAPSHOT_OFFSET,
+    SNAPSHOT_SIZE,
 };
-use save::SaveBuffer;
 use utils::print_info;
+#[cfg(debug_assertions)]
+use utils::FrameCost;
 
 mod game;
+mod rumble;
 mod save;
 mod utils;
 
+// For checking submitted runs off the console
+pub use game::{simulate_run, verify_run, FrameInput, HudLayout, RunResult, Settings, SpawnInfo};
+
+// Debug builds only, for checking what a scripted session did
+#[cfg(debug_assertions)]
+pub use game::{replay_transitions, StateTransition};
+
 pub mod constant {
     // GamePlay Config
     pub const MAX_JUMP_HEIGHT_PX: u16 = 45;
@@ -45,17 +58,156 @@ pub mod constant {
     pub const BIRD_SPAWN_INTERVAL_FRAMES: u16 = 60 * 5;
     pub const CACTUS_SPAWN_INTERVAL_FRAMES: u16 = 60 * 3;
     pub const LEVEL_UP_INTERVAL_FRAMES: u16 = 60 * 30;
+    // Game steps per rendered frame, more with the `fast_forward` feature for testing
+    #[cfg(not(feature = "fast_forward"))]
+    pub const STEPS_PER_VBLANK: u32 = 1;
+    #[cfg(feature = "fast_forward")]
+    pub const STEPS_PER_VBLANK: u32 = 4;
 }
 
 pub fn save(save_access: &mut SaveData, save_buffer: SaveBuffer) -> Result<(), Error> {
-    let mut writer = save_access.prepare_write(0..5)?;
-    writer.write(0, &save_buffer.as_array())
+    let mut writer = save_access.prepare_write(0..SAVE_SIZE)?;
+    // Read back, since SRAM accesses "succeed" on a cart that has some other chip
+    writer.write_and_verify(0, save_buffer.as_array())
+}
+
+// Keeps a copy of a save that failed its checksum, so the progress in it can still be dug out
+pub fn back_up(save_access: &mut SaveData, save_buffer: SaveBuffer) -> Result<(), Error> {
+    let mut writer =
+        save_access.prepare_write(SAVE_BACKUP_OFFSET..SAVE_BACKUP_OFFSET + SAVE_SIZE)?;
+    writer.write_and_verify(SAVE_BACKUP_OFFSET, save_buffer.as_array())
+}
+
+pub fn store_run(save_access: &mut SaveData, run: GameSnapshot) -> Result<(), Error> {
+    let mut writer = save_access.prepare_write(SNAPSHOT_OFFSET..SNAPSHOT_OFFSET + SNAPSHOT_SIZE)?;
+    writer.write_and_verify(SNAPSHOT_OFFSET, run.as_array())
+}
+
+// The red skin once it's bought, unless the options say otherwise
+fn chosen_skin(save_buffer: &SaveBuffer) -> Skin {
+    if save_buffer.is_unlocked(Unlock::RedSkin)
+        && !save_buffer.is_preferred(Preference::DefaultSkin)
+    {
+        Skin::Red
+    } else {
+        Skin::Default
+    }
+}
+
+// Without any save media the game carries on, keeping its scores in memory only
+fn save_or_log(
+    mgba: &mut Option<Mgba>,
+    save_access: &mut Option<SaveData>,
+    save_buffer: SaveBuffer,
+) {
+    let Some(save_access) = save_access else {
+        return;
+    };
+    let result = save(save_access, save_buffer);
+    if result.is_err() {
+        print_info(
+            mgba,
+            format_args!("[ERR] failed to write: {:?}", result.unwrap_err()),
+        );
+    }
+}
+
+fn store_run_or_log(
+    mgba: &mut Option<Mgba>,
+    save_access: &mut Option<SaveData>,
+    run: GameSnapshot,
+) {
+    let Some(save_access) = save_access else {
+        return;
+    };
+    if let Err(err) = store_run(save_access, run) {
+        print_info(mgba, format_args!("[ERR] failed to write run: {:?}", err));
+    }
+}
+
+// How every run is set up, so one carried on from a snapshot gets the same settings back
+fn run_settings(
+    difficulty: Difficulty,
+    players: u8,
+    tutorial: bool,
+    calm: bool,
+    save_buffer: &SaveBuffer,
+) -> Settings {
+    let settings = Settings {
+        // The tutorial keeps to the easy pace throughout
+        init_scroll_velocity: if tutorial {
+            Difficulty::Easy.init_scroll_velocity()
+        } else {
+            difficulty.init_scroll_velocity()
+        },
+        jump_height_px: MAX_JUMP_HEIGHT_PX,
+        jump_duration_frames: MAX_JUMP_DURATION_FRAMES,
+        fall_duration_frames: MAX_JUMP_DURATION_FRAMES,
+        min_jump_cut_multiplier: num!(0.4),
+        jump_cooldown_frames: 0,
+        max_enemies_displayed: 3,
+        show_time: true,
+        reduced_flashing: save_buffer.is_preferred(Preference::ReducedFlashing),
+        fast_enemy_warning_frames: 30,
+        extra_lives: save_buffer.is_unlocked(Unlock::ExtraLife) as u8,
+        extra_life_interval: 500,
+        max_combo: 5,
+        enable_birds: !tutorial,
+        enable_cactus: true,
+        bird_bob: false,
+        weather: true,
+        players,
+        rumble: save_buffer.is_preferred(Preference::Rumble),
+        confirm_restart: true,
+        restart_hold_frames: 45,
+        resume_countdown: true,
+        tutorial,
+        is_ranked: !tutorial,
+        extra_spawn_gap_px: 0,
+        collision_grace_px: 0,
+        ground_y: GROUND_Y,
+        hud: HudLayout::default(),
+        day_frames: 60 * 40,
+        night_frames: 60 * 20,
+        night_transition_frames: 60 * 2,
+        spawn_interval_frames: 60,
+        coin_spawn_interval_frames: 60 * 8,
+        animation_interval_frames: 10,
+        spawn_delay_factor: if tutorial {
+            Difficulty::Easy.spawn_delay_factor()
+        } else {
+            difficulty.spawn_delay_factor()
+        },
+        scroll_velocity_increase_per_level: if tutorial {
+            0.into()
+        } else {
+            difficulty.scroll_velocity_increase_per_level()
+        },
+        frames_to_level_up: 60 * 30,
+        max_speed_level: u16::MAX,
+        frames_per_point: 6,
+        speed_weighted_score: false,
+        hi_score: if tutorial || calm {
+            0
+        } else {
+            save_buffer.get_score(difficulty)
+        },
+        last_score: save_buffer.get_last_score(),
+        rng_seed: core::array::from_fn(|_| agb::rng::gen() as u32),
+        achievements: save_buffer.get_achievements(),
+        coin_balance: save_buffer.get_coins(),
+    };
+    if calm {
+        settings.calm()
+    } else {
+        settings
+    }
 }
 
 pub fn main(mut gba: agb::Gba) -> ! {
     let mut mgba = Mgba::new();
     let (mut oam, mut sprite_loader) = gba.display.object.get_unmanaged();
-    let sprite_cache = SpriteCache::new(&mut sprite_loader);
+    let mut sprite_cache = SpriteCache::new(&mut sprite_loader);
 
     let (bg_graphics, mut vram) = gba.display.video.tiled0();
     vram.set_background_palettes(BG_PALETTES);
@@ -63,21 +215,12 @@ pub fn main(mut gba: agb::Gba) -> ! {
     let tile_map = create_tile_map();
     let mut background = InfiniteScrolledMap::new(
         bg_graphics.background(
-            Priority::P0,
+            Priority::P1,
             RegularBackgroundSize::Background64x32,
             TileFormat::FourBpp,
         ),
         Box::new(|pos| {
-            let x = pos.x.rem_euclid(64) as u16;
-            let y = pos.y.rem_euclid(20) as u16;
-
-            let tile_idx = if y >= BG_TILES_OFFSET_Y && y < BG_TILES_OFFSET_Y + BG_TILES_HEIGHT {
-                *tile_map
-                    .get((x + 64 * (y - BG_TILES_OFFSET_Y)) as usize)
-                    .unwrap_or(&(BG_BLANK_TILE_IDX as usize)) as usize
-            } else {
-                BG_BLANK_TILE_IDX as usize
-            };
+            let tile_idx = background_tile(&tile_map, pos);
             (&BG_TILES_DATA.tiles, BG_TILES_DATA.tile_settings[tile_idx])
         }),
     );
@@ -86,78 +229,353 @@ pub fn main(mut gba: agb::Gba) -> ! {
     background.show();
     background.commit(&mut vram);
 
-    let mut mixer = gba.mixer.mixer(Frequency::Hz10512);
+    // In front of the ground, which is opaque all the way up through the sky
+    let mut rain = bg_graphics.background(
+        Priority::P0,
+        RegularBackgroundSize::Background32x32,
+        TileFormat::FourBpp,
+    );
+    rain.show();
+    rain.commit(&mut vram);
+
+    // Everything shows outside window 0, which only the restart wipe turns on
+    let mut windows = gba.display.window.get();
+    windows
+        .win_out()
+        .enable()
+        .set_background_enable(background.background(), true)
+        .set_background_enable(rain.background(), true)
+        .set_object_enable(true);
+    windows.commit();
+
+    let mut mixer = gba.mixer.mixer(MIXER_FREQUENCY);
     mixer.enable();
 
+    // The media is fixed when the ROM is built: only one kind can be declared to emulators and
+    // flashers, and SRAM can't be told apart from no chip at all at runtime. Flash is probed for
+    // its chip ID on first access, so a cart without it ends up with no save below.
+    #[cfg(not(feature = "flash_save"))]
     gba.save.init_sram();
-    let mut save_access = gba.save.access().unwrap();
+    #[cfg(feature = "flash_save")]
+    gba.save.init_flash_64k();
+    let mut save_access = match gba.save.access() {
+        Ok(save_access) => {
+            print_info(
+                &mut mgba,
+                format_args!("[init] save media: {:?}", save_access.media_type()),
+            );
+            Some(save_access)
+        }
+        Err(err) => {
+            print_info(
+                &mut mgba,
+                format_args!("[init] no save media, scores won't be kept: {:?}", err),
+            );
+            None
+        }
+    };
     let mut save_buffer = SaveBuffer::new();
-    save_access.read(0, save_buffer.as_mut_array()).unwrap();
+    if let Some(Err(err)) = save_access
+        .as_mut()
+        .map(|save_access| save_access.read(0, save_buffer.as_mut_array()))
+    {
+        print_info(&mut mgba, format_args!("[ERR] failed to read: {:?}", err));
+    }
     print_info(
         &mut mgba,
         format_args!("[init] saved data: {:?}", save_buffer),
     );
 
-    let mut hi_score = if save_buffer.is_savedata_exist() == false {
+    if !save_buffer.is_savedata_exist() {
         print_info(
             &mut mgba,
             format_args!("[init] initializing hi score save slot..."),
         );
-        let result = save(&mut save_access, SaveBuffer::new());
-        if result.is_err() {
+        save_buffer = SaveBuffer::new();
+        save_or_log(&mut mgba, &mut save_access, save_buffer);
+    } else if save_buffer.is_corrupt() {
+        // Played on as read rather than reset, so a bad byte costs at most what it held. The
+        // next save writes it back with a fresh checksum.
+        print_info(
+            &mut mgba,
+            format_args!("[ERR] save failed its checksum, backing it up..."),
+        );
+        if let Some(Err(err)) = save_access
+            .as_mut()
+            .map(|save_access| back_up(save_access, save_buffer))
+        {
             print_info(
                 &mut mgba,
-                format_args!("[ERR] failed to write: {:?}", result.unwrap_err()),
+                format_args!("[ERR] failed to back up: {:?}", err),
             );
         }
-        0
-    } else {
-        save_buffer.get_score()
-    };
+    } else if !save_buffer.is_current_version() {
+        print_info(
+            &mut mgba,
+            format_args!("[init] upgrading save from an older layout..."),
+        );
+        save_buffer = save_buffer.upgraded();
+        save_or_log(&mut mgba, &mut save_access, save_buffer);
+    }
+
+    let mut stored_run = GameSnapshot::empty();
+    if let Some(Err(err)) = save_access
+        .as_mut()
+        .map(|save_access| save_access.read(SNAPSHOT_OFFSET, stored_run.as_mut_array()))
+    {
+        print_info(
+            &mut mgba,
+            format_args!("[ERR] failed to read run: {:?}", err),
+        );
+    }
+    let mut stored_run = Some(stored_run).filter(GameSnapshot::is_stored);
+    // The stored run to carry on with instead of starting a new one
+    let mut resume = None;
 
     let vblank = agb::interrupt::VBlank::get();
+    #[cfg(debug_assertions)]
+    let mut timers = gba.timers.timers();
 
+    let mut difficulty = Difficulty::Normal;
+    let mut players = 1;
+    let mut tutorial = false;
+    let mut calm = false;
+    // Debug builds only, a stress run picked from the title's debug page
+    #[allow(unused_mut)]
+    let mut stress = false;
+    let mut show_title = true;
     loop {
-        let mut game = Game::from_settings(Settings {
-            init_scroll_velocity: num!(3.4),
-            jump_height_px: MAX_JUMP_HEIGHT_PX,
-            jump_duration_frames: MAX_JUMP_DURATION_FRAMES,
-            max_enemies_displayed: 3,
-            spawn_interval_frames: 60,
-            animation_interval_frames: 10,
-            scroll_velocity_increase_per_level: num!(0.15),
-            frames_to_level_up: 60 * 30,
-            hi_score,
-        });
+        if show_title {
+            sprite_cache.set_overlay_loaded(&mut sprite_loader, true);
+            let mut title = Title::new(difficulty, players, stored_run.is_some());
+            let event = loop {
+                let event = title.frame(&mut mixer);
+                mixer.frame();
+
+                vblank.wait_for_vblank();
+                title.render(&mut oam.iter(), &sprite_cache, &save_buffer);
+                match event {
+                    Some(TitleEvent::Options) => {
+                        let mut options = Options::new(Some(title.difficulty()));
+                        let saved = save_buffer;
+                        loop {
+                            let event = options.frame(&mut save_buffer);
+                            mixer.frame();
+                            if event == OptionsEvent::Changed {
+                                // Swapping the skin drops the overlay, which the menu draws with
+                                sprite_cache
+                                    .set_skin(&mut sprite_loader, chosen_skin(&save_buffer));
+                                sprite_cache.set_overlay_loaded(&mut sprite_loader, true);
+                            }
+
+                            vblank.wait_for_vblank();
+                            options.render(&mut oam.iter(), &sprite_cache, &save_buffer);
+                            if event == OptionsEvent::Exit {
+                                break;
+                            }
+                        }
+                        if let Some(difficulty) = options.difficulty() {
+                            title.set_difficulty(difficulty);
+                        }
+                        if save_buffer.as_array() != saved.as_array() {
+                            save_or_log(&mut mgba, &mut save_access, save_buffer);
+                        }
+                    }
+                    Some(event) => break event,
+                    None => {}
+                }
+            };
+            match event {
+                TitleEvent::Continue => {
+                    // A run is carried on once, so it's cleared from the save straight away
+                    resume = stored_run.take();
+                    store_run_or_log(&mut mgba, &mut save_access, GameSnapshot::empty());
+                    if let Some((run_difficulty, _)) = resume.as_ref().and_then(|run| run.read()) {
+                        difficulty = run_difficulty;
+                    }
+                    players = 1;
+                    tutorial = false;
+                    calm = false;
+                    stress = false;
+                }
+                TitleEvent::Start(chosen) => {
+                    difficulty = chosen;
+                    players = title.players();
+                    tutorial = title.is_tutorial();
+                    calm = title.is_calm();
+                    stress = false;
+                }
+                #[cfg(debug_assertions)]
+                TitleEvent::Stress => {
+                    players = 1;
+                    tutorial = false;
+                    calm = false;
+                    stress = true;
+                }
+                TitleEvent::Options => unreachable!(),
+            }
+            show_title = false;
+        }
+
+        sprite_cache.set_skin(&mut sprite_loader, chosen_skin(&save_buffer));
+
+        let settings = run_settings(difficulty, players, tutorial, calm, &save_buffer);
+        let mut game = resume
+            .take()
+            .and_then(|run| Game::from_snapshot(settings, run.read()?.1))
+            .unwrap_or_else(|| Game::from_settings(settings));
+        #[cfg(debug_assertions)]
+        if stress {
+            game.enable_stress();
+        }
+        let mut is_run_saved = false;
+        let is_ranked = settings.is_ranked;
+        #[cfg(debug_assertions)]
+        let mut frame_cost = FrameCost::new(stress);
+        // Final score of each player's run this session
+        let mut scores = [0; 2];
 
         loop {
-            let state = game.frame(&sprite_cache, &mut vram, &mut background, &mut mixer);
+            #[cfg(debug_assertions)]
+            frame_cost.start(&mut timers.timer2);
+            // Every step is a whole frame, however many run per vblank
+            let state = game.frame(
+                &mut vram,
+                &mut background,
+                &mut rain,
+                &mut mixer,
+                Number::new(1),
+                STEPS_PER_VBLANK,
+            );
+            sprite_cache.set_overlay_loaded(&mut sprite_loader, game.needs_overlay());
             mixer.frame();
 
+            #[cfg(debug_assertions)]
+            frame_cost.pause(&timers.timer2);
             vblank.wait_for_vblank();
-            let oam_frame = &mut oam.iter();
-            game.render(oam_frame, &sprite_cache);
+            #[cfg(debug_assertions)]
+            frame_cost.start(&mut timers.timer2);
+            game.render(&mut oam.iter(), &sprite_cache);
+            game.render_wipe(&mut windows);
+            windows.commit();
             background.commit(&mut vram);
+            #[cfg(debug_assertions)]
+            {
+                frame_cost.pause(&timers.timer2);
+                frame_cost.report(&mut mgba);
+            }
 
             match state {
                 GameState::Over(score) => {
-                    if score > hi_score {
-                        print_info(
-                            &mut mgba,
-                            format_args!("Hi score beat: {} -> {}", hi_score, score),
-                        );
-                        hi_score = score;
-                        let result = save(&mut save_access, hi_score.into());
-                        if result.is_err() {
+                    // Over is returned every frame until restart, bank the run only once
+                    if !is_run_saved {
+                        is_run_saved = true;
+                        scores[game.turn() as usize] = score;
+                        let hi_score = save_buffer.get_score(difficulty);
+                        if score > hi_score && is_ranked {
                             print_info(
                                 &mut mgba,
-                                format_args!("[ERR] failed to write: {:?}", result.unwrap_err()),
+                                format_args!("Hi score beat: {} -> {}", hi_score, score),
                             );
+                            save_buffer.set_score(difficulty, score);
+                        }
+                        if is_ranked {
+                            save_buffer.set_last_score(score);
+                            save_buffer.record_score(score);
+                            if game.distance_m() > save_buffer.get_best_distance() {
+                                save_buffer.set_best_distance(game.distance_m());
+                            }
+                        }
+                        save_buffer.add_coins(game.coins_collected());
+                        // Achievements wait for the end of the run too, so SRAM isn't written
+                        // mid-run
+                        if !stress {
+                            save_buffer.add_achievements(game.achievements());
+                        }
+                        save_or_log(&mut mgba, &mut save_access, save_buffer);
+                    }
+                }
+                GameState::Shop => {
+                    let mut shop = Shop::new();
+                    loop {
+                        let event = shop.frame(&mut save_buffer, &mut mixer);
+                        mixer.frame();
+
+                        vblank.wait_for_vblank();
+                        let oam_frame = &mut oam.iter();
+                        shop.render(oam_frame, &sprite_cache, &save_buffer);
+
+                        match event {
+                            ShopEvent::Purchased => {
+                                save_or_log(&mut mgba, &mut save_access, save_buffer);
+                            }
+                            ShopEvent::Exit => break,
+                            ShopEvent::None => {}
+                        }
+                    }
+                    print_info(&mut mgba, format_args!("Restarting.."));
+                    break;
+                }
+                GameState::Restart if players > 1 && game.turn() + 1 == players => {
+                    sprite_cache.set_overlay_loaded(&mut sprite_loader, true);
+                    let mut results = Results::new(scores);
+                    loop {
+                        let is_done = results.frame();
+                        mixer.frame();
+
+                        vblank.wait_for_vblank();
+                        results.render(&mut oam.iter(), &sprite_cache);
+                        if is_done {
+                            break;
                         }
                     }
+                    show_title = true;
+                    break;
                 }
                 GameState::Restart => {
                     print_info(&mut mgba, format_args!("Restarting.."));
+                    game.reset();
+                    is_run_saved = false;
+                }
+                GameState::Options => {
+                    let mut options = Options::new(None);
+                    let saved = save_buffer;
+                    loop {
+                        let event = options.frame(&mut save_buffer);
+                        mixer.frame();
+                        if event == OptionsEvent::Changed {
+                            sprite_cache.set_skin(&mut sprite_loader, chosen_skin(&save_buffer));
+                            sprite_cache.set_overlay_loaded(&mut sprite_loader, true);
+                        }
+
+                        vblank.wait_for_vblank();
+                        options.render(&mut oam.iter(), &sprite_cache, &save_buffer);
+                        if event == OptionsEvent::Exit {
+                            break;
+                        }
+                    }
+                    game.resume_from_options(
+                        save_buffer.is_preferred(Preference::Rumble),
+                        save_buffer.is_preferred(Preference::ReducedFlashing),
+                    );
+                    if save_buffer.as_array() != saved.as_array() {
+                        save_or_log(&mut mgba, &mut save_access, save_buffer);
+                    }
+                }
+                GameState::Quit => {
+                    // Kept to carry on from the title, unless it's one of several turns, an
+                    // unranked run or a stress run
+                    if players == 1 && is_ranked && !stress {
+                        let run = game.snapshot(difficulty);
+                        store_run_or_log(&mut mgba, &mut save_access, run);
+                        stored_run = Some(run);
+                    }
+                    if game.achievements() & !save_buffer.get_achievements() != 0 && !stress {
+                        save_buffer.add_achievements(game.achievements());
+                        save_or_log(&mut mgba, &mut save_access, save_buffer);
+                    }
+                    print_info(&mut mgba, format_args!("Quitting to the title.."));
+                    show_title = true;
                     break;
                 }
                 _ => {}
@@ -165,3 +583,10 @@ pub fn main(mut gba: agb::Gba) -> ! {
         }
     }
 }
+
+#[cfg(test)]
+#[agb::entry]
+fn agb_test_main(gba: agb::Gba) -> ! {
+    #[allow(clippy::empty_loop)]
+    loop {} // full implementation provided by the #[entry]
+}
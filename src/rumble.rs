@@ -0,0 +1,61 @@
+// Rumble through the cartridge's GPIO port, the way carts with a built-in motor (and
+// flashcarts that emulate one) are driven. On a cart without the port these writes land on ROM
+// and are ignored, so it's safe to leave running anywhere.
+const GPIO_DATA: *mut u16 = 0x0800_00C4 as *mut u16;
+const GPIO_DIRECTION: *mut u16 = 0x0800_00C6 as *mut u16;
+const GPIO_CONTROL: *mut u16 = 0x0800_00C8 as *mut u16;
+const MOTOR_PIN: u16 = 1 << 3;
+
+pub struct Rumble {
+    enabled: bool,
+    frames_left: u16,
+}
+
+impl Rumble {
+    pub fn new(enabled: bool) -> Self {
+        if enabled {
+            // SAFETY: the GPIO registers are write-only I/O mapped over the ROM header padding
+            unsafe {
+                GPIO_CONTROL.write_volatile(1);
+                GPIO_DIRECTION.write_volatile(MOTOR_PIN);
+            }
+        }
+        Self {
+            enabled,
+            frames_left: 0,
+        }
+    }
+
+    // Runs the motor for at least `frames` more frames, a no-op when turned off
+    pub fn pulse(&mut self, frames: u16) {
+        if !self.enabled || frames == 0 {
+            return;
+        }
+        if self.frames_left == 0 {
+            Self::set_motor(true);
+        }
+        self.frames_left = self.frames_left.max(frames);
+    }
+
+    // Call once a frame to stop the motor when the pulse is over
+    pub fn frame(&mut self) {
+        if self.frames_left > 0 {
+            self.frames_left -= 1;
+            if self.frames_left == 0 {
+                Self::set_motor(false);
+            }
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if self.frames_left > 0 {
+            self.frames_left = 0;
+            Self::set_motor(false);
+        }
+    }
+
+    fn set_motor(on: bool) {
+        // SAFETY: see `new`
+        unsafe { GPIO_DATA.write_volatile(if on { MOTOR_PIN } else { 0 }) };
+    }
+}
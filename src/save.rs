@@ -1,8 +1,107 @@
+use crate::game::{Achievement, Difficulty};
+
+// Layout:
+//   0      : 0 when the save exists
+//   1..13  : hi scores (LE), one per `Difficulty` in Easy, Normal, Hard order
+//   13..17 : lifetime coin balance (LE)
+//   17     : unlock flags, one bit per `Unlock`
+//   18     : achievement flags, one bit per `Achievement`
+//   19..23 : score of the last finished run (LE)
+//   23..27 : games played (LE)
+//   27..35 : sum of those games' scores (LE)
+//   35..39 : farthest distance run, in meters (LE)
+//   39     : preference flags, one bit per `Preference`
+//   40     : layout version
+//   41..43 : checksum, CRC-16 of bytes 0..41 (LE)
+pub const SAVE_SIZE: usize = 43;
+pub const SAVE_VERSION: u8 = 8;
+
+const SCORES_OFFSET: usize = 1;
+const COINS_OFFSET: usize = 13;
+const UNLOCKS_OFFSET: usize = 17;
+const ACHIEVEMENTS_OFFSET: usize = 18;
+const LAST_SCORE_OFFSET: usize = 19;
+const GAMES_PLAYED_OFFSET: usize = 23;
+const SCORE_SUM_OFFSET: usize = 27;
+const BEST_DISTANCE_OFFSET: usize = 35;
+const PREFERENCES_OFFSET: usize = 39;
+const VERSION_OFFSET: usize = 40;
+const CHECKSUM_OFFSET: usize = 41;
+
+// Older layouts. The original one was just the marker byte and a single hi score in 1..5,
+// version 1 kept that and added the coin balance in 5..9, unlocks in 9, version in 10 and
+// the checksum in 11. Version 2 is the current layout up to the unlock flags, followed by
+// its version in 18 and checksum in 19, and version 3 is the current layout up to the
+// achievement flags, followed by its version in 19 and checksum in 20. Version 4 added the
+// last score, with its version in 23 and checksum in 24, and version 5 the games played and
+// score sum, with its version in 35 and checksum in 36. Version 6 is the current layout with
+// a single XOR byte for the checksum, which misses any two bytes being swapped. Version 7 is
+// the current layout up to the distance, with its version in 39 and CRC in 40..42.
+const V7_VERSION_OFFSET: usize = 39;
+const V6_VERSION_OFFSET: usize = 39;
+const V5_VERSION_OFFSET: usize = 35;
+const V4_VERSION_OFFSET: usize = 23;
+const V3_VERSION_OFFSET: usize = 19;
+const V2_VERSION_OFFSET: usize = 18;
+const LEGACY_SCORE_OFFSET: usize = 1;
+const V1_COINS_OFFSET: usize = 5;
+const V1_UNLOCKS_OFFSET: usize = 9;
+const V1_VERSION_OFFSET: usize = 10;
+// Version offsets of the layouts from 6 down to 2, which all extend the current one
+const XOR_FOOTERS: [(usize, u8); 5] = [
+    (V6_VERSION_OFFSET, 6),
+    (V5_VERSION_OFFSET, 5),
+    (V4_VERSION_OFFSET, 4),
+    (V3_VERSION_OFFSET, 3),
+    (V2_VERSION_OFFSET, 2),
+];
+
+// A save that fails its CRC is copied here before anything else is written, in a sector of
+// its own past the stored run
+pub const SAVE_BACKUP_OFFSET: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unlock {
+    RedSkin,
+    ExtraLife,
+}
+
+impl Unlock {
+    fn mask(self) -> u8 {
+        match self {
+            Unlock::RedSkin => 0b01,
+            Unlock::ExtraLife => 0b10,
+        }
+    }
+}
+
+// Player choices that stick between power cycles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preference {
+    Rumble,
+    ReducedFlashing,
+    // Keeps the original colours even once the red skin is bought
+    DefaultSkin,
+}
+
+impl Preference {
+    fn mask(self) -> u8 {
+        match self {
+            Preference::Rumble => 0b1,
+            Preference::ReducedFlashing => 0b10,
+            Preference::DefaultSkin => 0b100,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
-pub struct SaveBuffer([u8; 5]);
+pub struct SaveBuffer([u8; SAVE_SIZE]);
 impl SaveBuffer {
     pub fn new() -> Self {
-        Self([0, 0, 0, 0, 0])
+        let mut buffer = Self([0; SAVE_SIZE]);
+        buffer.0[VERSION_OFFSET] = SAVE_VERSION;
+        buffer.update_checksum();
+        buffer
     }
 
     pub fn as_mut_array(&mut self) -> &mut [u8] {
@@ -16,27 +115,430 @@ impl SaveBuffer {
         self.0[0] == 0
     }
 
-    pub fn get_score(&self) -> u32 {
-        self.0[1..]
-            .into_iter()
-            .enumerate()
-            .fold(0, |acc, (index, byte)| {
-                acc | ((*byte as u32) << (index * 8))
-            })
+    // False for saves written with an older layout, see `upgraded`, or corrupted since
+    pub fn is_current_version(&self) -> bool {
+        self.has_crc_footer(VERSION_OFFSET, SAVE_VERSION)
+    }
+
+    // A current layout save whose CRC doesn't match, say from a write cut short. Older layouts
+    // are told apart by their own footers, since any byte can land on the version's offset.
+    pub fn is_corrupt(&self) -> bool {
+        self.0[VERSION_OFFSET] == SAVE_VERSION
+            && !self.is_current_version()
+            && !self.is_older_version()
+    }
+
+    // Whether the save was written with an older layout that `upgraded` knows
+    fn is_older_version(&self) -> bool {
+        self.has_crc_footer(V7_VERSION_OFFSET, 7)
+            || XOR_FOOTERS
+                .iter()
+                .any(|&(version_offset, version)| self.has_footer(version_offset, version))
+            || self.has_footer(V1_VERSION_OFFSET, 1)
+    }
+
+    // Whether the bytes up to `version_offset` are a save of `version`, with the CRC of
+    // everything up to and including the version in the two bytes after
+    fn has_crc_footer(&self, version_offset: usize, version: u8) -> bool {
+        self.0[version_offset] == version
+            && self.read_u16(version_offset + 1) == crc16(&self.0[..version_offset + 1])
+    }
+
+    // Whether the bytes up to `version_offset` are a save of `version` from before the CRC,
+    // with its XOR checksum in the byte right after
+    fn has_footer(&self, version_offset: usize, version: u8) -> bool {
+        let checksum = self.0[..version_offset + 1]
+            .iter()
+            .fold(0, |acc, byte| acc ^ byte);
+        self.0[version_offset] == version && self.0[version_offset + 1] == checksum
+    }
+
+    // Rebuilds an older save in the current layout. A single hi score goes to Normal, which
+    // was the only difficulty back then, and coins and unlocks are kept where they existed.
+    pub fn upgraded(&self) -> Self {
+        if self.has_crc_footer(V7_VERSION_OFFSET, 7) {
+            let mut buffer = Self::new();
+            buffer.0[..V7_VERSION_OFFSET].copy_from_slice(&self.0[..V7_VERSION_OFFSET]);
+            buffer.update_checksum();
+            return buffer;
+        }
+        for (version_offset, version) in XOR_FOOTERS {
+            if self.has_footer(version_offset, version) {
+                let mut buffer = Self::new();
+                buffer.0[..version_offset].copy_from_slice(&self.0[..version_offset]);
+                buffer.update_checksum();
+                return buffer;
+            }
+        }
+
+        let mut buffer =
+            Self::migrate_legacy(self.0[..LEGACY_SCORE_OFFSET + 4].try_into().unwrap());
+        if self.has_footer(V1_VERSION_OFFSET, 1) {
+            buffer.write_u32(COINS_OFFSET, self.read_u32(V1_COINS_OFFSET));
+            buffer.0[UNLOCKS_OFFSET] = self.0[V1_UNLOCKS_OFFSET];
+            buffer.update_checksum();
+        }
+        buffer
+    }
+
+    // The original layout, the marker byte and a single hi score. Everything added since starts
+    // out empty.
+    pub fn migrate_legacy(old: [u8; LEGACY_SCORE_OFFSET + 4]) -> Self {
+        let mut buffer = Self::new();
+        let score = u32::from_le_bytes(old[LEGACY_SCORE_OFFSET..].try_into().unwrap());
+        buffer.set_score(Difficulty::Normal, score);
+        buffer
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes(self.0[offset..offset + 2].try_into().unwrap())
+    }
+    fn read_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.0[offset..offset + 4].try_into().unwrap())
+    }
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        self.update_checksum();
+    }
+    fn read_u64(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.0[offset..offset + 8].try_into().unwrap())
+    }
+    fn write_u64(&mut self, offset: usize, value: u64) {
+        self.0[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        self.update_checksum();
+    }
+
+    fn score_offset(difficulty: Difficulty) -> usize {
+        let slot = match difficulty {
+            Difficulty::Easy => 0,
+            Difficulty::Normal => 1,
+            Difficulty::Hard => 2,
+        };
+        SCORES_OFFSET + slot * 4
+    }
+    pub fn get_score(&self, difficulty: Difficulty) -> u32 {
+        self.read_u32(Self::score_offset(difficulty))
+    }
+    pub fn set_score(&mut self, difficulty: Difficulty, score: u32) {
+        self.write_u32(Self::score_offset(difficulty), score);
+    }
+
+    pub fn get_last_score(&self) -> u32 {
+        self.read_u32(LAST_SCORE_OFFSET)
+    }
+    pub fn set_last_score(&mut self, score: u32) {
+        self.write_u32(LAST_SCORE_OFFSET, score);
+    }
+
+    pub fn get_games_played(&self) -> u32 {
+        self.read_u32(GAMES_PLAYED_OFFSET)
+    }
+    // Whole-number mean of every recorded score, 0 before the first game
+    pub fn get_average_score(&self) -> u32 {
+        let games = self.get_games_played();
+        if games == 0 {
+            return 0;
+        }
+        (self.read_u64(SCORE_SUM_OFFSET) / games as u64) as u32
+    }
+    // Counts a finished game towards the average. Once the count can't go any higher the
+    // average is left as it is, rather than adding scores it can no longer divide by.
+    pub fn record_score(&mut self, score: u32) {
+        let Some(games) = self.get_games_played().checked_add(1) else {
+            return;
+        };
+        let sum = self.read_u64(SCORE_SUM_OFFSET) + score as u64;
+        self.write_u32(GAMES_PLAYED_OFFSET, games);
+        self.write_u64(SCORE_SUM_OFFSET, sum);
+    }
+
+    pub fn get_best_distance(&self) -> u32 {
+        self.read_u32(BEST_DISTANCE_OFFSET)
+    }
+    pub fn set_best_distance(&mut self, meters: u32) {
+        self.write_u32(BEST_DISTANCE_OFFSET, meters);
+    }
+
+    pub fn get_coins(&self) -> u32 {
+        self.read_u32(COINS_OFFSET)
+    }
+    pub fn add_coins(&mut self, coins: u32) {
+        self.write_u32(COINS_OFFSET, self.get_coins().saturating_add(coins));
+    }
+    // Leaves the balance untouched and returns false if it can't cover the price
+    pub fn spend_coins(&mut self, coins: u32) -> bool {
+        match self.get_coins().checked_sub(coins) {
+            Some(balance) => {
+                self.write_u32(COINS_OFFSET, balance);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_unlocked(&self, unlock: Unlock) -> bool {
+        self.0[UNLOCKS_OFFSET] & unlock.mask() != 0
+    }
+    pub fn unlock(&mut self, unlock: Unlock) {
+        self.0[UNLOCKS_OFFSET] |= unlock.mask();
+        self.update_checksum();
+    }
+
+    pub fn is_preferred(&self, preference: Preference) -> bool {
+        self.0[PREFERENCES_OFFSET] & preference.mask() != 0
+    }
+    pub fn set_preference(&mut self, preference: Preference, enabled: bool) {
+        if enabled {
+            self.0[PREFERENCES_OFFSET] |= preference.mask();
+        } else {
+            self.0[PREFERENCES_OFFSET] &= !preference.mask();
+        }
+        self.update_checksum();
+    }
+
+    pub fn get_achievements(&self) -> u8 {
+        self.0[ACHIEVEMENTS_OFFSET]
+    }
+    pub fn is_achieved(&self, achievement: Achievement) -> bool {
+        self.0[ACHIEVEMENTS_OFFSET] & achievement.mask() != 0
+    }
+    // Only ever sets flags, so an achievement can't be lost once saved
+    pub fn add_achievements(&mut self, flags: u8) {
+        self.0[ACHIEVEMENTS_OFFSET] |= flags;
+        self.update_checksum();
+    }
+
+    fn checksum(&self) -> u16 {
+        crc16(&self.0[..CHECKSUM_OFFSET])
+    }
+    fn update_checksum(&mut self) {
+        let checksum = self.checksum();
+        self.0[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 2].copy_from_slice(&checksum.to_le_bytes());
     }
 }
 
-impl From<u32> for SaveBuffer {
-    fn from(value: u32) -> Self {
-        let mut arr: [u8; 5] = [0, 0, 0, 0, 0];
-        for (index, byte) in value.to_le_bytes().iter().enumerate() {
-            arr[index + 1] = *byte;
+// CRC-16/CCITT-FALSE (polynomial 0x1021, starting from 0xFFFF). Bit by bit rather than from a
+// table, the save is only a few dozen bytes.
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
         }
-        Self(arr)
+    }
+    crc
+}
+
+// A run quit from the pause screen, kept so it can be carried on after a power cycle. It has a
+// 4KiB sector of its own past the save, since preparing a flash write erases whole sectors.
+//   0                : `Difficulty` of the run, in the same order as the hi scores
+//   1..              : the run itself, field by field, see `Game::snapshot`
+//   SNAPSHOT_SIZE-3  : layout version, 0 when no run is stored
+//   SNAPSHOT_SIZE-2..: checksum, CRC-16 of everything before it (LE)
+pub const SNAPSHOT_OFFSET: usize = 4096;
+pub const SNAPSHOT_SIZE: usize = 256;
+// Goes up with any change to the fields or to what they mean, such as which enemy a queued spawn
+// byte turns into
+const SNAPSHOT_VERSION: u8 = 7;
+const SNAPSHOT_FIELDS_OFFSET: usize = 1;
+const SNAPSHOT_VERSION_OFFSET: usize = SNAPSHOT_SIZE - 3;
+const SNAPSHOT_CHECKSUM_OFFSET: usize = SNAPSHOT_SIZE - 2;
+
+#[derive(Clone, Copy)]
+pub struct GameSnapshot([u8; SNAPSHOT_SIZE]);
+impl GameSnapshot {
+    // Holds no run, and writing it out clears the stored one
+    pub fn empty() -> Self {
+        Self([0; SNAPSHOT_SIZE])
+    }
+
+    // `write_fields` has to fit everything in before the version
+    pub fn new(difficulty: Difficulty, write_fields: impl FnOnce(&mut SnapshotWriter)) -> Self {
+        let mut snapshot = Self::empty();
+        snapshot.0[0] = match difficulty {
+            Difficulty::Easy => 0,
+            Difficulty::Normal => 1,
+            Difficulty::Hard => 2,
+        };
+        write_fields(&mut SnapshotWriter(
+            &mut snapshot.0[SNAPSHOT_FIELDS_OFFSET..SNAPSHOT_VERSION_OFFSET],
+        ));
+        snapshot.0[SNAPSHOT_VERSION_OFFSET] = SNAPSHOT_VERSION;
+        let checksum = crc16(&snapshot.0[..SNAPSHOT_CHECKSUM_OFFSET]);
+        snapshot.0[SNAPSHOT_CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+        snapshot
+    }
+
+    pub fn as_mut_array(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+    pub fn as_array(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn is_stored(&self) -> bool {
+        self.read().is_some()
+    }
+
+    // The run's difficulty and its fields, unless there's no run or it's been corrupted
+    pub fn read(&self) -> Option<(Difficulty, SnapshotReader<'_>)> {
+        let checksum = u16::from_le_bytes(self.0[SNAPSHOT_CHECKSUM_OFFSET..].try_into().unwrap());
+        if self.0[SNAPSHOT_VERSION_OFFSET] != SNAPSHOT_VERSION
+            || checksum != crc16(&self.0[..SNAPSHOT_CHECKSUM_OFFSET])
+        {
+            return None;
+        }
+        let difficulty = match self.0[0] {
+            0 => Difficulty::Easy,
+            1 => Difficulty::Normal,
+            2 => Difficulty::Hard,
+            _ => return None,
+        };
+        Some((
+            difficulty,
+            SnapshotReader(&self.0[SNAPSHOT_FIELDS_OFFSET..SNAPSHOT_VERSION_OFFSET]),
+        ))
+    }
+}
+
+// Appends little endian fields, panicking once they run past the end
+pub struct SnapshotWriter<'a>(&'a mut [u8]);
+impl SnapshotWriter<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        let (field, rest) = core::mem::take(&mut self.0).split_at_mut(bytes.len());
+        field.copy_from_slice(bytes);
+        self.0 = rest;
+    }
+    pub fn u8(&mut self, value: u8) {
+        self.put(&[value]);
+    }
+    pub fn bool(&mut self, value: bool) {
+        self.put(&[value as u8]);
+    }
+    pub fn u16(&mut self, value: u16) {
+        self.put(&value.to_le_bytes());
+    }
+    pub fn u32(&mut self, value: u32) {
+        self.put(&value.to_le_bytes());
+    }
+    pub fn i32(&mut self, value: i32) {
+        self.put(&value.to_le_bytes());
+    }
+}
+
+// Takes the fields back in the order `SnapshotWriter` put them
+pub struct SnapshotReader<'a>(&'a [u8]);
+impl SnapshotReader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (field, rest) = self.0.split_at(N);
+        self.0 = rest;
+        field.try_into().unwrap()
+    }
+    pub fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+    pub fn bool(&mut self) -> bool {
+        self.u8() != 0
+    }
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+    pub fn i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.take())
+    }
+}
+
+impl Default for SaveBuffer {
+    fn default() -> Self {
+        Self::new()
     }
 }
-impl From<[u8; 5]> for SaveBuffer {
-    fn from(value: [u8; 5]) -> Self {
-        Self(value)
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn corrupt_current_save_is_not_upgraded(_gba: &mut agb::Gba) {
+        let mut save = SaveBuffer::new();
+        save.set_score(Difficulty::Hard, 1234);
+        assert!(save.is_current_version() && !save.is_corrupt());
+
+        save.0[SCORES_OFFSET] ^= 0xFF;
+        assert!(!save.is_current_version());
+        assert!(save.is_corrupt());
+    }
+
+    #[test_case]
+    fn older_save_is_not_corrupt(_gba: &mut agb::Gba) {
+        // A version 6 save whose XOR checksum happens to equal the current version
+        let mut save = SaveBuffer([0; SAVE_SIZE]);
+        save.0[V6_VERSION_OFFSET] = 6;
+        save.0[1] = SAVE_VERSION ^ 6;
+        save.0[V6_VERSION_OFFSET + 1] = SAVE_VERSION;
+        assert_eq!(save.0[VERSION_OFFSET], SAVE_VERSION);
+        assert!(!save.is_corrupt());
+        assert_eq!(
+            save.upgraded().get_score(Difficulty::Easy),
+            (SAVE_VERSION ^ 6) as u32
+        );
+    }
+
+    #[test_case]
+    fn last_score_round_trips(_gba: &mut agb::Gba) {
+        let mut save = SaveBuffer::new();
+        assert_eq!(save.get_last_score(), 0);
+        save.set_last_score(98765);
+        assert_eq!(save.get_last_score(), 98765);
+        assert!(save.is_current_version());
+    }
+
+    #[test_case]
+    fn average_of_recorded_scores(_gba: &mut agb::Gba) {
+        let mut save = SaveBuffer::new();
+        assert_eq!(save.get_average_score(), 0);
+        for score in [100, 250, 45] {
+            save.record_score(score);
+        }
+        assert_eq!(save.get_games_played(), 3);
+        // 395 / 3, rounded down
+        assert_eq!(save.get_average_score(), 131);
+
+        // With the count maxed out, further scores leave the average alone
+        save.write_u32(GAMES_PLAYED_OFFSET, u32::MAX);
+        let average = save.get_average_score();
+        save.record_score(u32::MAX);
+        assert_eq!(save.get_games_played(), u32::MAX);
+        assert_eq!(save.get_average_score(), average);
+    }
+
+    #[test_case]
+    fn crc16_known_values(_gba: &mut agb::Gba) {
+        // The standard check value for CRC-16/CCITT-FALSE
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[0; 4]), 0x84C0);
+        // Swapping two bytes changes it, which an XOR of them can't notice
+        assert_eq!(crc16(&[0x01, 0x02]), 0x0E7C);
+        assert_eq!(crc16(&[0x02, 0x01]), 0x6B4C);
+    }
+
+    #[test_case]
+    fn legacy_score_survives_migration(_gba: &mut agb::Gba) {
+        // The marker byte, then 0x00012345 little-endian
+        let save = SaveBuffer::migrate_legacy([0, 0x45, 0x23, 0x01, 0x00]);
+        assert!(save.is_current_version());
+        assert_eq!(save.get_score(Difficulty::Normal), 0x12345);
+        assert_eq!(save.get_score(Difficulty::Easy), 0);
+        assert_eq!(save.get_score(Difficulty::Hard), 0);
+        assert_eq!(save.get_coins(), 0);
+        assert_eq!(save.get_games_played(), 0);
     }
 }
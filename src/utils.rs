@@ -1,4 +1,6 @@
 use agb::mgba::{DebugLevel, Mgba};
+#[cfg(debug_assertions)]
+use agb::timer::{Divider, Timer};
 
 pub fn print_info(mgba: &mut Option<Mgba>, output: core::fmt::Arguments) {
     // Debug output
@@ -7,3 +9,71 @@ pub fn print_info(mgba: &mut Option<Mgba>, output: core::fmt::Arguments) {
         None => {}
     };
 }
+
+// Debug builds only: the time spent outside the vblank wait, in ticks of a 65.536kHz timer. A
+// frame has about 1092 of them before a vblank is missed. The timer wraps after a second, far
+// longer than any frame.
+#[cfg(debug_assertions)]
+#[derive(Default)]
+pub struct FrameCost {
+    // Off outside a stress run, when nothing is timed or logged
+    is_enabled: bool,
+    ticks: u32,
+    total: u32,
+    worst: u32,
+    frames: u32,
+}
+
+#[cfg(debug_assertions)]
+impl FrameCost {
+    const REPORT_FRAMES: u32 = 60;
+
+    pub fn new(is_enabled: bool) -> Self {
+        Self {
+            is_enabled,
+            ..Self::default()
+        }
+    }
+
+    pub fn start(&mut self, timer: &mut Timer) {
+        if !self.is_enabled {
+            return;
+        }
+        // Reenabling reloads the counter from 0
+        timer
+            .set_enabled(false)
+            .set_divider(Divider::Divider256)
+            .set_overflow_amount(0)
+            .set_enabled(true);
+    }
+
+    pub fn pause(&mut self, timer: &Timer) {
+        if !self.is_enabled {
+            return;
+        }
+        self.ticks += timer.value() as u32;
+    }
+
+    // Ends the frame, logging the average and worst of the last second's worth
+    pub fn report(&mut self, mgba: &mut Option<Mgba>) {
+        if !self.is_enabled {
+            return;
+        }
+        self.total += self.ticks;
+        self.worst = self.worst.max(self.ticks);
+        self.ticks = 0;
+        self.frames += 1;
+        if self.frames < Self::REPORT_FRAMES {
+            return;
+        }
+        print_info(
+            mgba,
+            format_args!(
+                "[cost] avg {} worst {} of 1092 ticks",
+                self.total / self.frames,
+                self.worst
+            ),
+        );
+        *self = Self::new(true);
+    }
+}